@@ -0,0 +1,302 @@
+use serde::Deserialize;
+use std::convert::TryFrom;
+use toml::Value;
+
+/// Keyframes for one number or vector in a scene file, interpolated between to animate it. The scene file's
+/// `[[tracks]]` tables are each one track:
+///
+/// ```toml
+/// [[tracks]]
+/// parameter = "camera.position"
+/// interpolation = "catmull-rom"
+/// keyframes = [
+///     { frame = 0, value = [2, 4, 4] },
+///     { frame = 30, value = [0, 1, 3] },
+///     { frame = 60, value = [-2, 0.5, 1] },
+/// ]
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Track {
+    /// Path to the value in the scene file: keys of nested tables and indices into arrays, separated by dots, eg.
+    /// `lights.0.position` or `object.c`
+    pub parameter: String,
+    #[serde(default)]
+    pub interpolation: Interpolation,
+    /// In order of frame. Before the first and after the last keyframe the value holds still
+    pub keyframes: Vec<Keyframe>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "KeyframeTable")]
+pub struct Keyframe {
+    /// Frame number, counting from 0. Doesn't have to be whole
+    pub frame: f64,
+    pub value: KeyValue,
+    /// Whether the value is written as integers in the scene file, eg. `2` rather than `2.0`
+    pub integer: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyframeTable {
+    frame: f64,
+    value: Value,
+}
+
+impl TryFrom<KeyframeTable> for Keyframe {
+    type Error = String;
+
+    fn try_from(table: KeyframeTable) -> Result<Self, Self::Error> {
+        let integer = is_integer(&table.value);
+        let value = table
+            .value
+            .try_into()
+            .map_err(|_| String::from("keyframe values must be numbers or arrays of numbers"))?;
+        Ok(Keyframe { frame: table.frame, value, integer })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum KeyValue {
+    Number(f64),
+    /// Colors, positions and quaternions. Each component is interpolated separately
+    Vector(Vec<f64>),
+}
+
+/// How a track moves between keyframes.
+#[derive(Debug, Copy, Clone, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Interpolation {
+    /// Straight from one keyframe to the next at a steady speed
+    #[default]
+    Linear,
+    /// Smooth curve through every keyframe, steering towards the keyframes either side so the motion has no sudden
+    /// changes of direction or speed as it passes through them
+    CatmullRom,
+    /// Speeds up out of each keyframe and slows down into the next, coming to rest at every keyframe
+    Ease,
+}
+
+impl Track {
+    /// Value of the track at `frame`.
+    pub fn value_at(&self, frame: f64) -> Result<KeyValue, String> {
+        self.check()?;
+        let keys = &self.keyframes;
+        let next = keys.iter().position(|key| key.frame > frame).unwrap_or(keys.len());
+        if next == 0 || next == keys.len() {
+            let held = if next == 0 { &keys[0] } else { &keys[keys.len() - 1] };
+            return Ok(held.value.clone());
+        }
+
+        let (before, after) = (&keys[next - 1], &keys[next]);
+        let s = (frame - before.frame) / (after.frame - before.frame);
+        let components = match self.interpolation {
+            Interpolation::Linear => lerp(&before.value, &after.value, s),
+            Interpolation::Ease => lerp(&before.value, &after.value, s * s * (3.0 - 2.0 * s)),
+            Interpolation::CatmullRom => {
+                // the ends of the track repeat their keyframe in place of the missing neighbour
+                let first = &keys[next.saturating_sub(2)];
+                let last = &keys[(next + 1).min(keys.len() - 1)];
+                catmull_rom([first, before, after, last], s)
+            }
+        };
+        Ok(match before.value {
+            KeyValue::Number(_) => KeyValue::Number(components[0]),
+            KeyValue::Vector(_) => KeyValue::Vector(components),
+        })
+    }
+
+    fn check(&self) -> Result<(), String> {
+        let first = self.keyframes.first().ok_or_else(|| format!("track {} has no keyframes", self.parameter))?;
+        for pair in self.keyframes.windows(2) {
+            if pair[1].frame <= pair[0].frame {
+                return Err(format!("keyframes of track {} are not in order of frame", self.parameter));
+            }
+            if components(&pair[1].value).len() != components(&first.value).len()
+                || matches!(pair[1].value, KeyValue::Number(_)) != matches!(first.value, KeyValue::Number(_)) {
+                return Err(format!("keyframes of track {} have different numbers of components", self.parameter));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn components(value: &KeyValue) -> &[f64] {
+    match value {
+        KeyValue::Number(n) => std::slice::from_ref(n),
+        KeyValue::Vector(v) => v,
+    }
+}
+
+fn lerp(a: &KeyValue, b: &KeyValue, s: f64) -> Vec<f64> {
+    components(a).iter().zip(components(b)).map(|(a, b)| a + (b - a) * s).collect()
+}
+
+/// Catmull-Rom spline between the middle two of four consecutive keyframes. The tangent at each keyframe is the slope
+/// between its neighbours, scaled to the length of the segment so unevenly spaced keyframes don't overshoot.
+fn catmull_rom([k0, k1, k2, k3]: [&Keyframe; 4], s: f64) -> Vec<f64> {
+    let segment = k2.frame - k1.frame;
+    // the neighbours are the keyframes themselves at the ends of the track, which the segment's length stands in for
+    let span1 = (k2.frame - k0.frame).max(segment);
+    let span2 = (k3.frame - k1.frame).max(segment);
+
+    // cubic Hermite basis
+    let s2 = s * s;
+    let s3 = s2 * s;
+    let h00 = 2.0 * s3 - 3.0 * s2 + 1.0;
+    let h10 = s3 - 2.0 * s2 + s;
+    let h01 = -2.0 * s3 + 3.0 * s2;
+    let h11 = s3 - s2;
+
+    let points = [components(&k0.value), components(&k1.value), components(&k2.value), components(&k3.value)];
+    (0..points[1].len()).map(|i| {
+        let tangent1 = (points[2][i] - points[0][i]) * segment / span1;
+        let tangent2 = (points[3][i] - points[1][i]) * segment / span2;
+        h00 * points[1][i] + h10 * tangent1 + h01 * points[2][i] + h11 * tangent2
+    }).collect()
+}
+
+/// Copy of the scene file's TOML with every track's value at `frame` written into it. Missing tables and keys along a
+/// track's path are added, so a track can animate a value the scene file leaves at its default. Values the scene file
+/// writes as integers, or that all of the track's keyframes write as integers, are rounded to integers so settings
+/// that only take whole numbers, like `marcher.aa_level`, can be animated too.
+pub fn apply(tracks: &[Track], scene: &Value, frame: f64) -> Result<Value, String> {
+    let mut scene = scene.clone();
+    for track in tracks {
+        let value = track.value_at(frame)?;
+        let target = lookup(&mut scene, &track.parameter)?;
+        let number = if is_integer(target) || track.keyframes.iter().all(|key| key.integer) {
+            |n: f64| Value::Integer(n.round() as i64)
+        } else {
+            Value::Float
+        };
+        *target = match value {
+            KeyValue::Number(n) => number(n),
+            KeyValue::Vector(v) => Value::Array(v.into_iter().map(number).collect()),
+        };
+    }
+    Ok(scene)
+}
+
+/// Whether a number, or every element of an array of numbers, is an integer.
+fn is_integer(value: &Value) -> bool {
+    match value {
+        Value::Integer(_) => true,
+        Value::Array(array) => !array.is_empty() && array.iter().all(Value::is_integer),
+        _ => false,
+    }
+}
+
+fn lookup<'a>(scene: &'a mut Value, parameter: &str) -> Result<&'a mut Value, String> {
+    let mut value = scene;
+    for key in parameter.split('.') {
+        value = match value {
+            Value::Table(table) => table.entry(key).or_insert_with(|| Value::Table(Default::default())),
+            Value::Array(array) => key.parse::<usize>().ok()
+                .and_then(move |i| array.get_mut(i))
+                .ok_or_else(|| format!("track {}: no array element {}", parameter, key))?,
+            _ => return Err(format!("track {}: {} is not in a table or array", parameter, key)),
+        };
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(interpolation: Interpolation, keys: &[(f64, f64)]) -> Track {
+        Track {
+            parameter: String::from("camera.fov"),
+            interpolation,
+            keyframes: keys
+                .iter()
+                .map(|&(frame, value)| Keyframe { frame, value: KeyValue::Number(value), integer: false })
+                .collect(),
+        }
+    }
+
+    fn number_at(track: &Track, frame: f64) -> f64 {
+        match track.value_at(frame).unwrap() {
+            KeyValue::Number(n) => n,
+            KeyValue::Vector(_) => panic!("expected a number"),
+        }
+    }
+
+    #[test]
+    fn interpolations_pass_through_keyframes() {
+        let keys = [(0.0, 1.0), (10.0, 3.0), (15.0, -2.0), (30.0, 0.0)];
+        for &interpolation in &[Interpolation::Linear, Interpolation::CatmullRom, Interpolation::Ease] {
+            let track = track(interpolation, &keys);
+            for &(frame, value) in &keys {
+                assert!((number_at(&track, frame) - value).abs() < 1E-9);
+            }
+            // held still outside the keyframes
+            assert_eq!(number_at(&track, -5.0), 1.0);
+            assert_eq!(number_at(&track, 40.0), 0.0);
+        }
+        assert!((number_at(&track(Interpolation::Linear, &keys), 5.0) - 2.0).abs() < 1E-9);
+        assert!((number_at(&track(Interpolation::Ease, &keys), 2.5) - 1.3125).abs() < 1E-9);
+    }
+
+    #[test]
+    fn catmull_rom_is_smooth_through_keyframes() {
+        let curve = track(Interpolation::CatmullRom, &[(0.0, 0.0), (10.0, 5.0), (14.0, 7.0), (30.0, 3.0)]);
+        for &frame in &[10.0, 14.0] {
+            let h = 1E-5;
+            let before = (number_at(&curve, frame) - number_at(&curve, frame - h)) / h;
+            let after = (number_at(&curve, frame + h) - number_at(&curve, frame)) / h;
+            assert!((before - after).abs() < 1E-3, "slope jumps from {} to {} at {}", before, after, frame);
+        }
+        // evenly spaced keyframes on a line are followed exactly
+        let line = track(Interpolation::CatmullRom, &[(0.0, 0.0), (1.0, 2.0), (2.0, 4.0), (3.0, 6.0)]);
+        assert!((number_at(&line, 1.5) - 3.0).abs() < 1E-9);
+    }
+
+    #[test]
+    fn tracks_write_into_the_scene() {
+        let source = "[[lights]]\ntype = \"ambient\"\n[camera]\nfov = 67.0\n[marcher]\naa_level = 1\n";
+        let scene: Value = toml::from_str(source).unwrap();
+        let tracks = vec![
+            Track {
+                parameter: String::from("lights.0.color"),
+                interpolation: Interpolation::Linear,
+                keyframes: vec![
+                    Keyframe { frame: 0.0, value: KeyValue::Vector(vec![0.0, 0.0, 0.0]), integer: true },
+                    Keyframe { frame: 4.0, value: KeyValue::Vector(vec![1.0, 0.5, 0.0]), integer: false },
+                ],
+            },
+            track(Interpolation::Linear, &[(0.0, 40.0), (4.0, 81.0)]),
+            Track {
+                parameter: String::from("shading.ao_strength"),
+                ..track(Interpolation::Linear, &[(0.0, 0.0), (4.0, 2.0)])
+            },
+            Track {
+                parameter: String::from("marcher.aa_level"),
+                ..track(Interpolation::Linear, &[(0.0, 1.0), (4.0, 4.0)])
+            },
+        ];
+        let scene = apply(&tracks, &scene, 1.0).unwrap();
+        assert_eq!(scene["lights"][0]["color"], Value::Array(vec![0.25.into(), 0.125.into(), 0.0.into()]));
+        assert_eq!(scene["camera"]["fov"], Value::Float(50.25));
+        // tables the scene file leaves out are added
+        assert_eq!(scene["shading"]["ao_strength"], Value::Float(0.5));
+        // integers in the scene file stay integers
+        assert_eq!(scene["marcher"]["aa_level"], Value::Integer(2));
+
+        let missing =
+            Track { parameter: String::from("lights.3.color"), ..track(Interpolation::Linear, &[(0.0, 1.0)]) };
+        assert!(apply(&[missing], &scene, 0.0).is_err());
+    }
+
+    #[test]
+    fn integer_keyframes_write_integers() {
+        let track: Track = toml::from_str("parameter = \"marcher.max_steps\"\nkeyframes = [\n\
+            { frame = 0, value = 100 },\n{ frame = 3, value = 200 },\n]").unwrap();
+        assert!(track.keyframes.iter().all(|key| key.integer));
+        let scene = apply(&[track], &Value::Table(Default::default()), 1.0).unwrap();
+        assert_eq!(scene["marcher"]["max_steps"], Value::Integer(133));
+    }
+}
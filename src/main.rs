@@ -1,13 +1,27 @@
-use crate::render_3d::fractals::{Julia, Mandelbulb};
-use crate::render_3d::raymarcher::RayMarcherConfig;
-use crate::render_3d::scene_object::Sphere;
+use crate::render_3d::camera::{Camera, Projection};
+use crate::render_3d::fractals::{OrbitSource, OrbitTrap, MANDELBROT_COLOR};
+use crate::palette::{Palette, PaletteDesc, Stop};
+use crate::render_3d::light::{Light, LightKind};
+use crate::render_3d::coloring::Overridden;
+use crate::render_3d::material::{Material, MaterialOverrides, Transparency};
+use crate::render_3d::raymarcher::{ImageRenderConfiguration, RayMarcherConfig};
+use crate::render_3d::scene_object::SceneObject;
+use crate::render_3d::stereo::Stereo;
+use crate::scene::{CameraDesc, MaterialRef, ObjectDesc, OrbitColoringDesc, SceneFile, ShadingDesc};
 use crate::vec3::Vec3;
 use cgmath::Quaternion;
-use clap::{App, AppSettings, Arg, SubCommand, Values};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand, Values};
+use std::fmt::Debug;
+use std::path::Path;
+use std::process::exit;
 use std::str::FromStr;
 
+mod animation;
+mod palette;
+mod range;
 mod render_2d;
 mod render_3d;
+mod scene;
 mod vec3;
 
 fn main() {
@@ -16,107 +30,62 @@ fn main() {
         .author("Liam Pribis <jackpribis@gmail.com>")
         .about("Julia Set Raymarcher")
         .setting(AppSettings::SubcommandRequiredElseHelp)
-        /* #region   */
         .subcommand(SubCommand::with_name("3d")
             .about("Render 3d julia set in window")
-            .arg(Arg::with_name("width")
-                .short("w")
-                .long("width")
-                .help("width of framebuffer")
-                .display_order(0)
+            .args(&render_args())
+        )
+        .subcommand(SubCommand::with_name("render")
+            .about("Render 3d julia set to a PNG file without opening a window")
+            .args(&render_args())
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .help("path of the PNG file to write")
+                .display_order(3)
                 .takes_value(true)
                 .required(true)
-                .validator(positive_int_validator)
+                .validator(png_path_validator)
             )
-            .arg(Arg::with_name("height")
-                .short("h")
-                .long("height")
-                .help("height of framebuffer")
-                .display_order(1)
+        )
+        .subcommand(SubCommand::with_name("animate")
+            .about("Render 3d julia set to a numbered sequence of PNG files, sweeping the time parameter")
+            .args(&render_args())
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .help("file name of each frame. The frame number is substituted into {} or a padded {:04}")
+                .display_order(3)
                 .takes_value(true)
                 .required(true)
-                .validator(positive_int_validator)
+                .validator(frame_name_validator)
             )
-            .arg(Arg::with_name("c")
-                .short("c")
-                .long("c")
-                .help("c value of julia set")
-                .display_order(2)
-                .require_equals(true)
-                .required(true)
-                .multiple(true)
-                .number_of_values(4)
-                .require_delimiter(true)
-                .value_delimiter(",")
-                .value_names(&["cw", "cx", "cy", "cz"])
+            .arg(Arg::with_name("t-start")
+                .long("t-start")
+                .help("value of t at the first frame")
+                .display_order(4)
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .default_value("0")
                 .validator(float_validator)
             )
-            .arg(optional_vec3_arg(
-                "camera-pos",
-                "position of camera in 3d space",
-                "2,4,4",
-                false,
-            ))
-            .arg(optional_vec3_arg(
-                "look-at",
-                "position to point camera towards in 3d space",
-                "0,0,0",
-                false,
-            ))
-            .arg(optional_vec3_arg(
-                "light-pos",
-                "position of light in 3d space",
-                "2,4,4",
-                false,
-            ))
-            .arg(optional_vec3_arg(
-                "bg-color",
-                "normalized (each element in [0, 1]) color of background",
-                "0,0,0",
-                true,
-            ))
-            .arg(optional_vec3_arg(
-                "backplane",
-                "values of x/y/z where rays will be assumed to be a miss (ie. back clipping planes)",
-                "3,3,3",
-                false,
-            ))
-            .arg(optional_vec3_arg(
-                "specular-color",
-                "normalized specular highlight color of the render",
-                "1,1,1",
-                true,
-            ))
-            .arg(optional_vec3_arg(
-                "object-color",
-                "normalized ambient color of the julia set",
-                "0.8,0,0",
-                true,
-            ))
-            .arg(Arg::with_name("zoom")
-                .short("z")
-                .long("zoom")
-                .help("camera zoom")
+            .arg(Arg::with_name("t-stop")
+                .long("t-stop")
+                .help("value of t that the sweep stops at (the last frame is one step before it)")
+                .display_order(5)
                 .takes_value(true)
+                .allow_hyphen_values(true)
                 .default_value("1")
                 .validator(float_validator)
             )
-            .arg(Arg::with_name("aa-level")
-                .long("aa-level")
-                .help("level of anti-aliasing. --aa-level 2 will provide a 2x2 subpixel grid")
+            .arg(Arg::with_name("frames")
+                .long("frames")
+                .help("number of frames to render")
+                .display_order(6)
                 .takes_value(true)
-                .default_value("2")
+                .required(true)
                 .validator(positive_int_validator)
             )
-            .arg(Arg::with_name("specular-shininess")
-                .long("specular-shininess")
-                .help("Phong shininess value used when calculating specular highlights")
-                .takes_value(true)
-                .default_value("50")
-                .validator(positive_float_validator)
-            )
         )
-        /* #endregion */
         .subcommand(SubCommand::with_name("2d")
             .about("render complex or quaternion julia sets in sliced 2D")
             .arg(Arg::with_name("width")
@@ -151,73 +120,727 @@ fn main() {
                 .value_names(&["cw", "cx", "cy", "cz"])
                 .validator(float_validator)
             )
+            .arg(palette_arg("grayscale", "palette to color the iteration count with").display_order(3))
         )
         .get_matches();
 
     if let Some(matches) = matches.subcommand_matches("3d") {
-        // all unwrapping should be OK because all args have validators and default values
-        let config = RayMarcherConfig {
-            camera_pos: matches.values_of("camera-pos").into(),
-            look_at: matches.values_of("look-at").into(),
-            light_pos: matches.values_of("light-pos").into(),
-            background_color: matches.values_of("bg-color").into(),
-            camera_zoom: matches.value_of("zoom").into_f64(),
-            anti_aliasing_level: matches.value_of("aa-level").into_u32(),
-            backplane_positions: matches.values_of("backplane").into(),
-            specular_shininess: matches.value_of("specular-shininess").into_f64(),
-            specular_color: matches.values_of("specular-color").into(),
-            ..Default::default()
-        };
+        let (config, object) = build_scene(matches, 0);
+        println!("{:#?}", config);
+        let (width, height) = frame_size(matches);
+
+        render_3d::main(width, height, config, object);
+    }
 
+    if let Some(matches) = matches.subcommand_matches("render") {
+        let (config, object) = build_scene(matches, 0);
         println!("{:#?}", config);
+        let (width, height) = frame_size(matches);
+        let output = matches.value_of("output").unwrap();
 
-        let mut c = matches.values_of("c").unwrap();
-        let c = Quaternion::new(
-            c.next().into_f64(),
-            c.next().into_f64(),
-            c.next().into_f64(),
-            c.next().into_f64(),
-        );
-
-        let width = matches.value_of("width").into_u32() as usize;
-        let height = matches.value_of("height").into_u32() as usize;
-        let object = Julia {
-            color: matches.values_of("object-color").into(),
-            c,
+        match render_3d::render(width, height, config, object, Path::new(output)) {
+            Ok(paths) => {
+                for path in paths {
+                    println!("rendered image {}", path.display());
+                }
+            }
+            Err(e) => {
+                eprintln!("could not render to {}: {}", output, e);
+                exit(1);
+            }
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("animate") {
+        let (width, height) = frame_size(matches);
+
+        let t_start = matches.value_of("t-start").into_f64();
+        let t_stop = matches.value_of("t-stop").into_f64();
+        let frames = matches.value_of("frames").into_u32();
+        let output = matches.value_of("output").unwrap();
+
+        let image_config = ImageRenderConfiguration {
+            width,
+            height,
+            t_start,
+            t_step: (t_stop - t_start) / frames as f64,
+            frames,
+            image_name: |i| format_frame_name(output, i).unwrap(),
         };
 
-        // let object = Mandelbulb {
-        //     color: matches.values_of("object-color").into()
-        // };
-        //
-        // let object = Sphere {
-        //     radius: 1.0,
-        //     center: (0, 0, 0).into(),
-        //     color: (1, 0, 0).into()
-        // };
+        let scene = |frame| {
+            let (config, object) = build_scene(matches, frame);
+            if frame == 0 {
+                println!("{:#?}", config);
+            }
+            (config, object)
+        };
 
-        render_3d::main(width, height, config, object);
+        if let Err(e) = render_3d::animate(scene, image_config) {
+            eprintln!("could not render animation: {}", e);
+            exit(1);
+        }
     }
 
     if let Some(matches) = matches.subcommand_matches("2d") {
-        let width = matches.value_of("width").into_u32() as usize;
-        let height = matches.value_of("height").into_u32() as usize;
-        let mut c = matches.values_of("c").unwrap();
-        let c = Quaternion::new(
-            c.next().into_f64(),
-            c.next().into_f64(),
-            c.next().into_f64(),
-            c.next().into_f64(),
-        );
+        let c = quaternion_arg(matches, "c");
+        let palette = matches.value_of("palette").unwrap().parse().unwrap();
+
+        render_2d::main(c, palette);
+    }
+}
 
-        render_2d::main(c);
+/// Arguments shared by every subcommand that renders the 3d scene.
+fn render_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("width")
+            .short("w")
+            .long("width")
+            .help("width of framebuffer")
+            .display_order(0)
+            .takes_value(true)
+            .required(true)
+            .validator(positive_int_validator),
+        Arg::with_name("height")
+            .short("h")
+            .long("height")
+            .help("height of framebuffer")
+            .display_order(1)
+            .takes_value(true)
+            .required(true)
+            .validator(positive_int_validator),
+        Arg::with_name("scene")
+            .short("s")
+            .long("scene")
+            .help("TOML scene file to load. Options given on the command line override the file")
+            .display_order(2)
+            .takes_value(true),
+        Arg::with_name("c")
+            .short("c")
+            .long("c")
+            .help("c value of julia set")
+            .display_order(2)
+            .require_equals(true)
+            .multiple(true)
+            .number_of_values(4)
+            .require_delimiter(true)
+            .value_delimiter(",")
+            .value_names(&["cw", "cx", "cy", "cz"])
+            .validator(float_validator),
+        optional_vec3_arg(
+            "camera-pos",
+            "position of camera in 3d space",
+            "2,4,4",
+            false,
+        ),
+        optional_vec3_arg(
+            "look-at",
+            "position to point camera towards in 3d space",
+            "0,0,0",
+            false,
+        ),
+        Arg::with_name("aperture")
+            .long("aperture")
+            .help("radius of the camera's lens. Larger apertures blur anything away from the focus distance more")
+            .takes_value(true)
+            .default_value("0")
+            .validator(non_negative_float_validator),
+        Arg::with_name("focus-distance")
+            .long("focus-distance")
+            .help("distance along the view direction that is in focus with --aperture [default: distance to \
+                --look-at]")
+            .takes_value(true)
+            .validator(positive_float_validator),
+        Arg::with_name("focus-on-hit")
+            .long("focus-on-hit")
+            .help("set the focus distance to the surface seen through this pixel of the image")
+            .require_equals(true)
+            .multiple(true)
+            .number_of_values(2)
+            .require_delimiter(true)
+            .value_delimiter(",")
+            .value_names(&["x", "y"])
+            .conflicts_with("focus-distance")
+            .validator(non_negative_int_validator),
+        Arg::with_name("light")
+            .long("light")
+            .help("add a light, given as its type (point, directional, spot, hemisphere or ambient) followed by \
+                :key=value properties, eg. point:position=2,4,4:color=1,1,1:intensity=1:falloff or \
+                spot:position=0,4,0:direction=0,-1,0:angle=30. Can be repeated \
+                [default: point:position=2,4,4 and ambient:color=0.1,0.1,0.1]")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .use_delimiter(false)
+            .allow_hyphen_values(true)
+            .validator(light_validator),
+        optional_vec3_arg(
+            "bg-color",
+            "normalized (each element in [0, 1]) color of background",
+            "0,0,0",
+            true,
+        ),
+        optional_vec3_arg(
+            "backplane",
+            "values of x/y/z where rays will be assumed to be a miss (ie. back clipping planes)",
+            "3,3,3",
+            false,
+        ),
+        optional_vec3_arg(
+            "specular-color",
+            "normalized color of the specular highlights on the object",
+            "1,1,1",
+            true,
+        ),
+        Arg::with_name("object")
+            .long("object")
+            .help("type of object to render")
+            .takes_value(true)
+            .possible_values(&["julia", "mandelbrot", "mandelbulb", "sphere"])
+            .default_value("julia"),
+        Arg::with_name("power")
+            .long("power")
+            .help("power of the mandelbulb (--object mandelbulb), greater than 1")
+            .takes_value(true)
+            .default_value("4")
+            .validator(power_validator),
+        Arg::with_name("w")
+            .long("w")
+            .help("w component of the 3d slice through the quaternion mandelbrot set (--object mandelbrot)")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .default_value("0")
+            .validator(float_validator),
+        Arg::with_name("radius")
+            .long("radius")
+            .help("radius of the sphere (--object sphere)")
+            .takes_value(true)
+            .default_value("1")
+            .validator(positive_float_validator),
+        optional_vec3_arg(
+            "object-color",
+            "normalized base color (albedo) of the object. The mandelbrot set is 0.5,0.5,1 unless this is given",
+            "0.8,0,0",
+            true,
+        ),
+        Arg::with_name("orbit-coloring")
+            .long("orbit-coloring")
+            .help("color fractals by the smoothed iteration count, or by how close their orbits come to a trap at the \
+                origin (point, sphere of radius 1) or along the y axis (plane, axis)")
+            .takes_value(true)
+            .possible_values(&["off", "iterations", "point-trap", "plane-trap", "axis-trap", "sphere-trap"])
+            .default_value("off"),
+        palette_arg("fire", "palette used by --orbit-coloring"),
+        Arg::with_name("orbit-color")
+            .long("orbit-color")
+            .help("deprecated: use --palette. Normalized color that --orbit-coloring blends the object color towards")
+            .require_equals(true)
+            .multiple(true)
+            .number_of_values(3)
+            .require_delimiter(true)
+            .value_delimiter(",")
+            .value_names(&["r", "g", "b"])
+            .validator(color_validator)
+            .conflicts_with("palette"),
+        optional_vec3_arg(
+            "up",
+            "direction that appears upwards in the image",
+            "0,1,0",
+            false,
+        ),
+        Arg::with_name("fov")
+            .long("fov")
+            .help("vertical field of view of the camera in degrees, between 0 and 180")
+            .takes_value(true)
+            .default_value("90")
+            .validator(fov_validator),
+        Arg::with_name("zoom")
+            .short("z")
+            .long("zoom")
+            .help("deprecated: use --fov. Camera zoom, the same as a --fov of 2·atan(1/zoom) in degrees")
+            .takes_value(true)
+            .validator(positive_float_validator)
+            .conflicts_with("fov"),
+        Arg::with_name("roll")
+            .long("roll")
+            .help("rotation of the camera about its view direction in degrees")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .default_value("0")
+            .validator(float_validator),
+        Arg::with_name("projection")
+            .long("projection")
+            .help("camera projection. orthographic cameras have no perspective and show an area --view-width wide. \
+                equirectangular shows every direction around the camera on a 2:1 image, fisheye shows --fisheye-angle \
+                degrees in a circle, and cube renders the six faces of a cube map in the --cube-layout")
+            .takes_value(true)
+            .possible_values(&["perspective", "orthographic", "equirectangular", "fisheye", "cube"])
+            .default_value("perspective"),
+        Arg::with_name("view-width")
+            .long("view-width")
+            .help("width of the area shown by --projection orthographic")
+            .takes_value(true)
+            .default_value("4")
+            .validator(positive_float_validator),
+        Arg::with_name("fisheye-angle")
+            .long("fisheye-angle")
+            .help("angle in degrees across the image circle of --projection fisheye. may be more than 180, up to 360")
+            .takes_value(true)
+            .default_value("180")
+            .validator(fisheye_angle_validator),
+        Arg::with_name("cube-layout")
+            .long("cube-layout")
+            .help("layout of --projection cube. cross unfolds the faces onto one 4:3 image, faces writes each face \
+                to its own square image named after the face, eg. out_front.png")
+            .takes_value(true)
+            .possible_values(&["cross", "faces"])
+            .default_value("cross"),
+        Arg::with_name("stereo")
+            .long("stereo")
+            .help("render a stereo pair from two eyes either side of the camera, with the eyes' images side by side \
+                (twice as wide), one above the other (twice as high) or combined into a red/cyan anaglyph. Only for \
+                the perspective projection")
+            .takes_value(true)
+            .possible_values(&["side-by-side", "top-bottom", "anaglyph"]),
+        Arg::with_name("ipd")
+            .long("ipd")
+            .help("interpupillary distance of --stereo: how far apart the eyes are, in scene units")
+            .takes_value(true)
+            .default_value("0.1")
+            .validator(positive_float_validator),
+        Arg::with_name("convergence")
+            .long("convergence")
+            .help("distance along the view direction at which the views of the eyes of --stereo line up, which \
+                appears at the depth of the screen [default: distance to --look-at]")
+            .takes_value(true)
+            .validator(positive_float_validator),
+        Arg::with_name("aa-level")
+            .long("aa-level")
+            .help("level of anti-aliasing. --aa-level 2 will provide a 2x2 subpixel grid")
+            .takes_value(true)
+            .default_value("2")
+            .validator(positive_int_validator),
+        Arg::with_name("specular-shininess")
+            .long("specular-shininess")
+            .help("Phong shininess of the object. Larger values give smaller, sharper specular highlights")
+            .takes_value(true)
+            .default_value("50")
+            .validator(positive_float_validator),
+        Arg::with_name("shading")
+            .long("shading")
+            .help("shading model. phong and blinn use the object's specular color and shininess, pbr is a physically \
+                based model using its metallic and roughness")
+            .takes_value(true)
+            .possible_values(&["phong", "blinn", "pbr"])
+            .default_value("phong"),
+        Arg::with_name("metallic")
+            .long("metallic")
+            .help("how metallic the object is for --shading pbr, from 0 (a dielectric like plastic) to 1 (a metal)")
+            .takes_value(true)
+            .default_value("0")
+            .validator(unit_interval_validator),
+        Arg::with_name("roughness")
+            .long("roughness")
+            .help("roughness of the object, from 0 (smooth) to 1. Dims reflections and, with --shading pbr, spreads \
+                out highlights")
+            .takes_value(true)
+            .default_value("0")
+            .validator(unit_interval_validator),
+        Arg::with_name("shadows")
+            .long("shadows")
+            .help("shadows cast by the light. soft shadows get a penumbra")
+            .takes_value(true)
+            .possible_values(&["hard", "soft", "off"])
+            .default_value("off"),
+        Arg::with_name("shadow-softness")
+            .long("shadow-softness")
+            .help("width of soft shadow penumbras. Larger is softer")
+            .takes_value(true)
+            .default_value("0.05")
+            .validator(positive_float_validator),
+        optional_vec3_arg(
+            "ambient-color",
+            "normalized color of the ambient light that reaches every surface. Replaces any ambient lights",
+            "0.1,0.1,0.1",
+            true,
+        ),
+        Arg::with_name("ao-strength")
+            .long("ao-strength")
+            .help("strength of ambient occlusion darkening crevices. 0 disables it")
+            .takes_value(true)
+            .default_value("1")
+            .validator(non_negative_float_validator),
+        Arg::with_name("ao-samples")
+            .long("ao-samples")
+            .help("number of distance samples taken along the normal for ambient occlusion")
+            .takes_value(true)
+            .default_value("5")
+            .validator(positive_int_validator),
+        Arg::with_name("max-bounces")
+            .long("max-bounces")
+            .help("maximum number of times a ray is reflected or refracted. 0 disables reflections and transparency")
+            .takes_value(true)
+            .default_value("3")
+            .validator(non_negative_int_validator),
+        Arg::with_name("reflectivity")
+            .long("reflectivity")
+            .help("reflectivity of the object when looked at straight on, from 0 (none) to 1 (a mirror)")
+            .takes_value(true)
+            .default_value("0")
+            .validator(unit_interval_validator),
+        Arg::with_name("ior")
+            .long("ior")
+            .help("makes the object transparent, with this index of refraction (eg. 1.5 for glass)")
+            .takes_value(true)
+            .validator(ior_validator),
+        optional_vec3_arg(
+            "transmission",
+            "fraction of each color channel left after light travels one unit through a transparent object",
+            "1,1,1",
+            true,
+        ),
+        Arg::with_name("integrator")
+            .long("integrator")
+            .help("how lighting is computed. whitted is direct lighting with mirror reflections and refractions, path \
+                is Monte Carlo path tracing with global illumination (noisy, see --spp)")
+            .takes_value(true)
+            .possible_values(&["whitted", "path"])
+            .default_value("whitted"),
+        Arg::with_name("spp")
+            .long("spp")
+            .help("samples (paths) per pixel for --integrator path. More samples give less noise. Replaces --aa-level")
+            .takes_value(true)
+            .default_value("16")
+            .validator(positive_int_validator),
+        Arg::with_name("seed")
+            .long("seed")
+            .help("seed for the random numbers of --integrator path and --aperture. The same seed renders the same \
+                image")
+            .takes_value(true)
+            .default_value("0")
+            .validator(seed_validator),
+        Arg::with_name("max-steps")
+            .long("max-steps")
+            .help("maximum number of steps to march a ray before giving up on it")
+            .takes_value(true)
+            .default_value("200")
+            .validator(positive_int_validator),
+        Arg::with_name("min-step")
+            .long("min-step")
+            .help("minimum distance to advance a ray each step, so NaN or negative distance estimates can't stall it")
+            .takes_value(true)
+            .default_value("0.00001")
+            .validator(positive_float_validator),
+        Arg::with_name("max-steps-color")
+            .long("max-steps-color")
+            .help("normalized color of rays that run out of steps [default: same as --bg-color]")
+            .require_equals(true)
+            .multiple(true)
+            .number_of_values(3)
+            .require_delimiter(true)
+            .value_delimiter(",")
+            .value_names(&["r", "g", "b"])
+            .validator(color_validator),
+    ]
+}
+
+/// Load `frame` of the scene file (if any) and merge it with the command line into a config and an object to render.
+/// Only animations have more than frame 0. Exits the process if the scene can't be loaded.
+fn build_scene(matches: &ArgMatches, frame: u32) -> (RayMarcherConfig, Box<dyn SceneObject>) {
+    let scene = match matches.value_of("scene") {
+        Some(path) => SceneFile::load(Path::new(path), frame).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            exit(1);
+        }),
+        None => SceneFile::default(),
+    };
+
+    let mut config = build_config(matches, &scene);
+    let object = build_object(matches, &scene).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        exit(1);
+    });
+    // the eyes shift a flat image plane apart, which the other projections don't have
+    if config.stereo.is_some() && config.camera.projection != Projection::Perspective {
+        eprintln!("stereo needs the perspective projection");
+        exit(1);
+    }
+
+    if let Some(mut pixel) = matches.values_of("focus-on-hit") {
+        let x = pixel.next().into_u32() as usize;
+        let y = pixel.next().into_u32() as usize;
+        let (width, height) = frame_size(matches);
+        if x >= width || y >= height {
+            eprintln!("could not focus on pixel {},{}: the image is only {}x{}", x, y, width, height);
+            exit(1);
+        }
+        match render_3d::focus_distance_at(&config, &object, (x, y), (width, height)) {
+            Some(distance) => config.camera.focus_distance = Some(distance),
+            None => {
+                eprintln!("could not focus on pixel {},{}: nothing is hit there", x, y);
+                exit(1);
+            }
+        }
+    }
+
+    (config, object)
+}
+
+fn build_config(matches: &ArgMatches, scene: &SceneFile) -> RayMarcherConfig {
+    // all unwrapping should be OK because all args have validators and default values
+    RayMarcherConfig {
+        camera: build_camera(matches, &scene.camera),
+        lights: lights_arg(matches, &scene.lights, scene.shading.ambient_color),
+        background_color: vec3_arg(matches, "bg-color", scene.shading.background_color),
+        anti_aliasing_level: u32_arg(matches, "aa-level", scene.marcher.aa_level),
+        backplane_positions: vec3_arg(matches, "backplane", scene.marcher.backplane),
+        shading: parsed_arg(matches, "shading", scene.shading.model),
+        max_ray_steps: u32_arg(matches, "max-steps", scene.marcher.max_steps),
+        min_step_size: f64_arg(matches, "min-step", scene.marcher.min_step),
+        max_steps_color: if matches.is_present("max-steps-color") {
+            Some(matches.values_of("max-steps-color").into())
+        } else {
+            scene.shading.max_steps_color
+        },
+        shadows: parsed_arg(matches, "shadows", scene.shading.shadows),
+        shadow_softness: f64_arg(matches, "shadow-softness", scene.shading.shadow_softness),
+        ao_strength: f64_arg(matches, "ao-strength", scene.shading.ao_strength),
+        ao_samples: u32_arg(matches, "ao-samples", scene.shading.ao_samples),
+        max_bounces: u32_arg(matches, "max-bounces", scene.shading.max_bounces),
+        integrator: parsed_arg(matches, "integrator", scene.shading.integrator),
+        samples_per_pixel: u32_arg(matches, "spp", scene.shading.spp),
+        seed: parsed_arg(matches, "seed", scene.shading.seed),
+        stereo: stereo_arg(matches, &scene.camera),
+    }
+}
+
+fn stereo_arg(matches: &ArgMatches, camera: &CameraDesc) -> Option<Stereo> {
+    let layout = match matches.value_of("stereo") {
+        Some(layout) => layout.parse().unwrap(),
+        None => camera.stereo?,
+    };
+    Some(Stereo {
+        ipd: f64_arg(matches, "ipd", camera.ipd),
+        convergence: match matches.value_of("convergence") {
+            Some(distance) => Some(distance.parse().unwrap()),
+            None => camera.convergence,
+        },
+        layout,
+    })
+}
+
+fn build_camera(matches: &ArgMatches, camera: &CameraDesc) -> Camera {
+    Camera {
+        position: vec3_arg(matches, "camera-pos", camera.position),
+        look_at: vec3_arg(matches, "look-at", camera.look_at),
+        up: vec3_arg(matches, "up", camera.up),
+        roll: f64_arg(matches, "roll", camera.roll),
+        projection: parsed_arg(matches, "projection", camera.projection),
+        fov: fov_arg(matches, camera),
+        view_width: f64_arg(matches, "view-width", camera.view_width),
+        fisheye_angle: f64_arg(matches, "fisheye-angle", camera.fisheye_angle),
+        cube_layout: parsed_arg(matches, "cube-layout", camera.cube_layout),
+        cube_face: None,
+        aperture: f64_arg(matches, "aperture", camera.aperture),
+        focus_distance: match matches.value_of("focus-distance") {
+            Some(distance) => Some(distance.parse().unwrap()),
+            None => camera.focus_distance,
+        },
+        image_shift: 0.0,
+    }
+}
+
+/// An explicit --object or --c builds the object from the command line, otherwise the scene file's object tree is
+/// used, with any material options given on the command line replacing those of its materials.
+fn build_object(matches: &ArgMatches, scene: &SceneFile) -> Result<Box<dyn SceneObject>, String> {
+    let from_command_line = matches.occurrences_of("object") > 0 || matches.is_present("c");
+    if let (false, Some(object)) = (from_command_line, &scene.object) {
+        let object = object.build(scene)?;
+        let overrides = material_overrides_arg(matches, &scene.shading);
+        return Ok(if overrides.is_empty() { object } else { Box::new(Overridden { object, overrides }) });
+    }
+
+    let material = material_arg(matches, &scene.shading);
+    let object = match matches.value_of("object").unwrap() {
+        "julia" => {
+            if !matches.is_present("c") {
+                return Err(String::from("--c is required to render a julia set"));
+            }
+            let c = quaternion_arg(matches, "c");
+            ObjectDesc::Julia {
+                c: [c.s, c.v.x, c.v.y, c.v.z],
+                coloring: orbit_coloring_arg(matches, material.albedo),
+                material: MaterialRef::Inline(material),
+            }
+        }
+        "mandelbrot" => {
+            let material = if matches.occurrences_of("object-color") > 0 {
+                material
+            } else {
+                Material { albedo: MANDELBROT_COLOR, ..material }
+            };
+            ObjectDesc::Mandelbrot {
+                w: matches.value_of("w").into_f64(),
+                coloring: orbit_coloring_arg(matches, material.albedo),
+                material: MaterialRef::Inline(material),
+            }
+        }
+        "mandelbulb" => ObjectDesc::Mandelbulb {
+            power: matches.value_of("power").into_f64(),
+            coloring: orbit_coloring_arg(matches, material.albedo),
+            material: MaterialRef::Inline(material),
+        },
+        "sphere" => ObjectDesc::Sphere {
+            center: Vec3::default(),
+            radius: matches.value_of("radius").into_f64(),
+            material: MaterialRef::Inline(material),
+        },
+        _ => unreachable!("clap only accepts the listed object types"),
+    };
+    object.build(scene)
+}
+
+fn material_arg(matches: &ArgMatches, shading: &ShadingDesc) -> Material {
+    Material {
+        albedo: matches.values_of("object-color").into(),
+        specular_color: vec3_arg(matches, "specular-color", shading.specular_color),
+        shininess: f64_arg(matches, "specular-shininess", shading.specular_shininess),
+        metallic: matches.value_of("metallic").into_f64(),
+        reflectivity: matches.value_of("reflectivity").into_f64(),
+        roughness: matches.value_of("roughness").into_f64(),
+        transparency: matches.value_of("ior").map(|ior| Transparency {
+            ior: ior.parse().unwrap(),
+            transmission: matches.values_of("transmission").into(),
+        }),
+        ..Material::default()
+    }
+}
+
+/// Material options given explicitly on the command line replace the properties of every material of a scene file's
+/// object, as do the scene file's own `specular_color` and `specular_shininess` shading settings.
+fn material_overrides_arg(matches: &ArgMatches, shading: &ShadingDesc) -> MaterialOverrides {
+    let given = |name| matches.occurrences_of(name) > 0;
+    MaterialOverrides {
+        albedo: given("object-color").then(|| matches.values_of("object-color").into()),
+        specular_color: given("specular-color").then(|| matches.values_of("specular-color").into())
+            .or(shading.specular_color),
+        shininess: given("specular-shininess").then(|| matches.value_of("specular-shininess").into_f64())
+            .or(shading.specular_shininess),
+        metallic: given("metallic").then(|| matches.value_of("metallic").into_f64()),
+        reflectivity: given("reflectivity").then(|| matches.value_of("reflectivity").into_f64()),
+        roughness: given("roughness").then(|| matches.value_of("roughness").into_f64()),
+        ior: given("ior").then(|| matches.value_of("ior").into_f64()),
+        transmission: given("transmission").then(|| matches.values_of("transmission").into()),
     }
 }
 
+/// Traps from the command line sit at the origin, or along the y axis. Scene files can place them anywhere. The older
+/// --orbit-color stands for a palette from the object's color to the orbit color.
+fn orbit_coloring_arg(matches: &ArgMatches, object_color: Vec3) -> Option<OrbitColoringDesc> {
+    let up = Vec3::from((0, 1, 0));
+    let source = match matches.value_of("orbit-coloring").unwrap() {
+        "off" => return None,
+        "iterations" => OrbitSource::Iterations,
+        "point-trap" => OrbitSource::Trap { trap: OrbitTrap::Point { position: Vec3::default() } },
+        "plane-trap" => OrbitSource::Trap { trap: OrbitTrap::Plane { normal: up, offset: 0.0 } },
+        "axis-trap" => OrbitSource::Trap { trap: OrbitTrap::Axis { point: Vec3::default(), direction: up } },
+        "sphere-trap" => OrbitSource::Trap { trap: OrbitTrap::Sphere { center: Vec3::default(), radius: 1.0 } },
+        _ => unreachable!("clap only accepts the listed coloring types"),
+    };
+    let palette = if matches.occurrences_of("orbit-color") > 0 {
+        Palette::Linear(vec![
+            Stop { position: 0.0, color: object_color },
+            Stop { position: 1.0, color: matches.values_of("orbit-color").into() },
+        ])
+    } else {
+        matches.value_of("palette").unwrap().parse().unwrap()
+    };
+    Some(OrbitColoringDesc { source, palette: PaletteDesc::Inline(palette) })
+}
+
+// The *_arg helpers pick an argument given explicitly on the command line first, then the scene file's value, and
+// finally the argument's default.
+
+fn vec3_arg(matches: &ArgMatches, name: &str, scene_value: Option<Vec3>) -> Vec3 {
+    match scene_value {
+        Some(value) if matches.occurrences_of(name) == 0 => value,
+        _ => matches.values_of(name).into(),
+    }
+}
+
+fn f64_arg(matches: &ArgMatches, name: &str, scene_value: Option<f64>) -> f64 {
+    match scene_value {
+        Some(value) if matches.occurrences_of(name) == 0 => value,
+        _ => matches.value_of(name).into_f64(),
+    }
+}
+
+fn u32_arg(matches: &ArgMatches, name: &str, scene_value: Option<u32>) -> u32 {
+    match scene_value {
+        Some(value) if matches.occurrences_of(name) == 0 => value,
+        _ => matches.value_of(name).into_u32(),
+    }
+}
+
+/// For arguments that parse into some other type, eg. an enum restricted to `possible_values`.
+fn parsed_arg<T: FromStr>(matches: &ArgMatches, name: &str, scene_value: Option<T>) -> T
+    where T::Err: Debug {
+    match scene_value {
+        Some(value) if matches.occurrences_of(name) == 0 => value,
+        _ => matches.value_of(name).unwrap().parse().unwrap(),
+    }
+}
+
+/// Lights given on the command line replace the scene file's lights rather than adding to them. An ambient color, from
+/// the command line or the scene file, replaces the ambient lights with one of that color.
+fn lights_arg(matches: &ArgMatches, scene_lights: &Option<Vec<Light>>, scene_ambient: Option<Vec3>) -> Vec<Light> {
+    let mut lights = match (matches.values_of("light"), scene_lights) {
+        (Some(lights), _) => lights.map(|light| light.parse().unwrap()).collect(),
+        (None, Some(lights)) => lights.clone(),
+        (None, None) => RayMarcherConfig::default().lights,
+    };
+    if matches.occurrences_of("ambient-color") > 0 || scene_ambient.is_some() {
+        lights.retain(|light| !matches!(light.kind, LightKind::Ambient));
+        lights.push(Light {
+            kind: LightKind::Ambient,
+            color: vec3_arg(matches, "ambient-color", scene_ambient),
+            intensity: 1.0,
+            falloff: false,
+        });
+    }
+    lights
+}
+
+/// Zoom, from the command line or the scene file, is the older way of giving the field of view. It was the distance
+/// from the camera to an image plane two units high.
+fn fov_arg(matches: &ArgMatches, camera: &CameraDesc) -> f64 {
+    let zoom_fov = |zoom: f64| 2.0 * zoom.recip().atan().to_degrees();
+    if matches.occurrences_of("zoom") > 0 {
+        return zoom_fov(matches.value_of("zoom").into_f64());
+    }
+    f64_arg(matches, "fov", camera.fov.or_else(|| camera.zoom.map(zoom_fov)))
+}
+
+fn frame_size(matches: &ArgMatches) -> (usize, usize) {
+    let width = matches.value_of("width").into_u32() as usize;
+    let height = matches.value_of("height").into_u32() as usize;
+    (width, height)
+}
+
+fn quaternion_arg(matches: &ArgMatches, name: &str) -> Quaternion<f64> {
+    let mut values = matches.values_of(name).unwrap();
+    Quaternion::new(
+        values.next().into_f64(),
+        values.next().into_f64(),
+        values.next().into_f64(),
+        values.next().into_f64(),
+    )
+}
+
 fn positive_int_validator(input: String) -> Result<(), String> {
     let int = input
         .parse::<u32>()
-        .map_err(|e| "must be a valid integer")?;
+        .map_err(|_| "must be a valid integer")?;
     if int > 0 {
         Ok(())
     } else {
@@ -225,6 +848,79 @@ fn positive_int_validator(input: String) -> Result<(), String> {
     }
 }
 
+fn non_negative_int_validator(input: String) -> Result<(), String> {
+    input
+        .parse::<u32>()
+        .map(|_| ())
+        .map_err(|_| String::from("must be a valid integer"))
+}
+
+fn seed_validator(input: String) -> Result<(), String> {
+    input
+        .parse::<u64>()
+        .map(|_| ())
+        .map_err(|_| String::from("must be a valid integer"))
+}
+
+fn frame_name_validator(input: String) -> Result<(), String> {
+    format_frame_name(&input, 0).and_then(png_path_validator)
+}
+
+/// Images are always written as PNG, so any other extension would be misleading.
+fn png_path_validator(input: String) -> Result<(), String> {
+    match Path::new(&input).extension().and_then(|extension| extension.to_str()) {
+        Some(extension) if extension.eq_ignore_ascii_case("png") => Ok(()),
+        _ => Err(String::from("only PNG images can be written, so the file name must end in .png")),
+    }
+}
+
+/// Substitute the frame number into the `{}` or `{:04}` style placeholder of an output file name.
+fn format_frame_name(template: &str, frame: u32) -> Result<String, String> {
+    let start = template
+        .find('{')
+        .ok_or("must contain a {} placeholder for the frame number")?;
+    let len = template[start..]
+        .find('}')
+        .ok_or("frame number placeholder is missing its closing }")?;
+    let spec = &template[start + 1..start + len];
+
+    let number = if spec.is_empty() {
+        frame.to_string()
+    } else if let Some(width) = spec.strip_prefix(':') {
+        let padding = width
+            .parse::<usize>()
+            .map_err(|_| format!("invalid frame number placeholder {{{}}}", spec))?;
+        if width.starts_with('0') {
+            format!("{:0padding$}", frame, padding = padding)
+        } else {
+            format!("{:padding$}", frame, padding = padding)
+        }
+    } else {
+        return Err(format!("invalid frame number placeholder {{{}}}", spec));
+    };
+
+    Ok(format!("{}{}{}", &template[..start], number, &template[start + len + 1..]))
+}
+
+fn palette_arg(default: &'static str, help: &'static str) -> Arg<'static, 'static> {
+    Arg::with_name("palette")
+        .long("palette")
+        .help(help)
+        .long_help(PALETTE_HELP)
+        .takes_value(true)
+        .default_value(default)
+        .validator(|input| input.parse::<Palette>().map(|_| ()))
+}
+
+const PALETTE_HELP: &str = "palette to color with: one of the presets grayscale, fire, ice, viridis, rainbow, cosine \
+    or sunset, or the path of a palette file. A palette file starts with a line naming its kind (linear, hsv or \
+    cosine). Linear and hsv palettes follow with one \"position r g b\" stop per line, cosine palettes with the \
+    lines \"a r g b\", \"b r g b\", \"c r g b\" and \"d r g b\" for the colors a + b * cos(2pi(c * t + d))";
+
+fn light_validator(input: String) -> Result<(), String> {
+    input.parse::<Light>().map(|_| ())
+}
+
 fn float_validator(input: String) -> Result<(), String> {
     Ok(input
         .parse::<f64>()
@@ -232,6 +928,70 @@ fn float_validator(input: String) -> Result<(), String> {
         .map_err(|_| "must be a valid float")?)
 }
 
+/// Each component of a normalized color.
+fn color_validator(input: String) -> Result<(), String> {
+    let f = input.parse::<f64>().map_err(|_| "must be a valid float")?;
+    if (0.0..=1.0).contains(&f) {
+        Ok(())
+    } else {
+        Err(String::from("color components must be between 0 and 1"))
+    }
+}
+
+fn unit_interval_validator(input: String) -> Result<(), String> {
+    let f = input.parse::<f64>().map_err(|_| "must be a valid float")?;
+    if (0.0..=1.0).contains(&f) {
+        Ok(())
+    } else {
+        Err(String::from("value must be between 0 and 1"))
+    }
+}
+
+fn fov_validator(input: String) -> Result<(), String> {
+    let f = input.parse::<f64>().map_err(|_| "must be a valid float")?;
+    if f > 0.0 && f < 180.0 {
+        Ok(())
+    } else {
+        Err(String::from("field of view must be between 0 and 180 degrees"))
+    }
+}
+
+fn fisheye_angle_validator(input: String) -> Result<(), String> {
+    let f = input.parse::<f64>().map_err(|_| "must be a valid float")?;
+    if f > 0.0 && f <= 360.0 {
+        Ok(())
+    } else {
+        Err(String::from("fisheye angle must be greater than 0 and up to 360 degrees"))
+    }
+}
+
+fn non_negative_float_validator(input: String) -> Result<(), String> {
+    let f = input.parse::<f64>().map_err(|_| "must be a valid float")?;
+    if f >= 0.0 {
+        Ok(())
+    } else {
+        Err(String::from("value must not be negative"))
+    }
+}
+
+fn power_validator(input: String) -> Result<(), String> {
+    let f = input.parse::<f64>().map_err(|_| "must be a valid float")?;
+    if f > 1.0 {
+        Ok(())
+    } else {
+        Err(String::from("power must be greater than 1"))
+    }
+}
+
+fn ior_validator(input: String) -> Result<(), String> {
+    let f = input.parse::<f64>().map_err(|_| "must be a valid float")?;
+    if f >= 1.0 {
+        Ok(())
+    } else {
+        Err(String::from("index of refraction must be at least 1"))
+    }
+}
+
 fn positive_float_validator(input: String) -> Result<(), String> {
     let f = input.parse::<f64>().map_err(|_| "must be a valid float")?;
     if f > 0.0 {
@@ -288,5 +1048,55 @@ fn optional_vec3_arg(
             &["x", "y", "z"]
         })
         .default_value(default)
-        .validator(float_validator)
+        .validator(if is_color { color_validator } else { float_validator })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses the render options, after the required image size.
+    fn parse(args: &[&str]) -> clap::Result<ArgMatches<'static>> {
+        let size = ["marcher", "-w", "8", "-h", "8"];
+        App::new("marcher").args(&render_args()).get_matches_from_safe(size.iter().chain(args))
+    }
+
+    #[test]
+    fn palette_and_orbit_color_only_conflict_when_both_are_given() {
+        let matches = parse(&["--orbit-coloring", "point-trap", "--palette", "ice"]).unwrap();
+        assert_eq!(matches.value_of("palette"), Some("ice"));
+        assert_eq!(matches.occurrences_of("orbit-color"), 0);
+        assert!(parse(&["--orbit-color=0,1,0"]).is_ok());
+        assert!(parse(&["--palette", "ice", "--orbit-color=0,1,0"]).is_err());
+    }
+
+    #[test]
+    fn output_must_be_png() {
+        assert!(png_path_validator(String::from("out/frame.png")).is_ok());
+        assert!(png_path_validator(String::from("frame.PNG")).is_ok());
+        assert!(png_path_validator(String::from("frame.jpg")).is_err());
+        assert!(png_path_validator(String::from("frame")).is_err());
+        assert!(frame_name_validator(String::from("frame_{:04}.png")).is_ok());
+        assert!(frame_name_validator(String::from("frame_{:04}.jpg")).is_err());
+    }
+
+    #[test]
+    fn ior_is_at_least_one() {
+        assert!(parse(&["--ior", "1"]).is_ok());
+        assert!(parse(&["--ior", "0.5"]).is_err());
+    }
+
+    #[test]
+    fn power_is_greater_than_one() {
+        assert!(parse(&["--power", "1.5"]).is_ok());
+        assert!(parse(&["--power", "1"]).is_err());
+        assert!(parse(&["--power", "-8"]).is_err());
+    }
+
+    #[test]
+    fn fisheye_angle_is_at_most_a_full_turn() {
+        assert!(parse(&["--fisheye-angle", "360"]).is_ok());
+        assert!(parse(&["--fisheye-angle", "400"]).is_err());
+        assert!(parse(&["--fisheye-angle", "0"]).is_err());
+    }
 }
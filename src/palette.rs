@@ -0,0 +1,429 @@
+use crate::vec3::Vec3;
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::f64::consts::PI;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Maps a value from 0 to 1 onto a color.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Palette {
+    /// Straight lines between the stops in RGB
+    Linear(Vec<Stop>),
+    /// Blends between the stops in HSV, taking the shorter way around the hue circle. Keeps blends between saturated
+    /// colors saturated instead of passing through gray
+    Hsv(Vec<Stop>),
+    /// `a + b * cos(2π(c * t + d))` per channel. Repeats outside of 0 to 1 rather than clamping
+    Cosine { a: Vec3, b: Vec3, c: Vec3, d: Vec3 },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(from = "[f64; 4]")]
+pub struct Stop {
+    pub position: f64,
+    pub color: Vec3,
+}
+
+impl From<[f64; 4]> for Stop {
+    fn from([position, r, g, b]: [f64; 4]) -> Self {
+        Stop { position, color: Vec3 { x: r, y: g, z: b } }
+    }
+}
+
+pub const PRESETS: &[&str] = &["grayscale", "fire", "ice", "viridis", "rainbow", "cosine", "sunset"];
+
+impl Palette {
+    pub fn color(&self, t: f64) -> Vec3 {
+        match self {
+            Palette::Linear(stops) => {
+                let (from, to, amount) = Self::segment(stops, t);
+                from.lerp(to, amount)
+            }
+            Palette::Hsv(stops) => {
+                let (from, to, amount) = Self::segment(stops, t);
+                hsv_lerp(from, to, amount)
+            }
+            Palette::Cosine { a, b, c, d } => Vec3 {
+                x: a.x + b.x * (2.0 * PI * (c.x * t + d.x)).cos(),
+                y: a.y + b.y * (2.0 * PI * (c.y * t + d.y)).cos(),
+                z: a.z + b.z * (2.0 * PI * (c.z * t + d.z)).cos(),
+            },
+        }
+    }
+
+    /// Colors of the stops either side of `t` and how far `t` is between them. Stops must be sorted by position.
+    fn segment(stops: &[Stop], t: f64) -> (Vec3, Vec3, f64) {
+        let first = stops[0];
+        let last = stops[stops.len() - 1];
+        if t <= first.position {
+            return (first.color, first.color, 0.0);
+        }
+        if t >= last.position {
+            return (last.color, last.color, 0.0);
+        }
+
+        let next = stops.iter().position(|stop| stop.position > t).unwrap();
+        let (from, to) = (stops[next - 1], stops[next]);
+        (from.color, to.color, (t - from.position) / (to.position - from.position))
+    }
+
+    /// One of the built in palettes listed in `PRESETS`.
+    pub fn preset(name: &str) -> Option<Palette> {
+        let stops = |colors: &[(f64, f64, f64)]| {
+            let step = 1.0 / (colors.len() - 1) as f64;
+            colors
+                .iter()
+                .enumerate()
+                .map(|(i, &color)| Stop { position: i as f64 * step, color: color.into() })
+                .collect()
+        };
+
+        let palette = match name {
+            "grayscale" => Palette::Linear(stops(&[(0.0, 0.0, 0.0), (1.0, 1.0, 1.0)])),
+            "fire" => Palette::Linear(stops(&[
+                (0.0, 0.0, 0.0),
+                (0.5, 0.0, 0.0),
+                (1.0, 0.4, 0.0),
+                (1.0, 0.9, 0.3),
+                (1.0, 1.0, 1.0),
+            ])),
+            "ice" => Palette::Linear(stops(&[
+                (0.0, 0.0, 0.1),
+                (0.0, 0.3, 0.6),
+                (0.5, 0.8, 1.0),
+                (1.0, 1.0, 1.0),
+            ])),
+            "viridis" => Palette::Linear(stops(&[
+                (0.267, 0.005, 0.329),
+                (0.230, 0.322, 0.546),
+                (0.128, 0.567, 0.551),
+                (0.369, 0.789, 0.383),
+                (0.993, 0.906, 0.144),
+            ])),
+            "rainbow" => Palette::Hsv(stops(&[(1.0, 0.0, 0.0), (0.0, 1.0, 0.0), (0.0, 0.0, 1.0)])),
+            "cosine" => Palette::Cosine {
+                a: 0.5.into(),
+                b: 0.5.into(),
+                c: 1.0.into(),
+                d: (0.0, 0.33, 0.67).into(),
+            },
+            "sunset" => Palette::Cosine {
+                a: 0.5.into(),
+                b: 0.5.into(),
+                c: (1.0, 1.0, 0.5).into(),
+                d: (0.8, 0.9, 0.3).into(),
+            },
+            _ => return None,
+        };
+        Some(palette)
+    }
+
+    /// The preset called `name`, or otherwise the palette file at the path `name` relative to `dir`.
+    pub fn named(name: &str, dir: &Path) -> Result<Palette, String> {
+        let path = dir.join(name);
+        match Palette::preset(name) {
+            Some(palette) => Ok(palette),
+            None if path.exists() => Palette::load(&path).map_err(|e| e.to_string()),
+            None => Err(format!(
+                "'{}' is neither a palette file nor one of the presets {}",
+                path.display(),
+                PRESETS.join(", ")
+            )),
+        }
+    }
+
+    /// Reads a palette file. The first line names the kind of palette, `linear`, `hsv` or `cosine`. Linear and HSV
+    /// palettes follow it with one `position r g b` stop per line, cosine palettes with lines `a r g b`, `b r g b`,
+    /// `c r g b` and `d r g b`. Blank lines and anything after a `#` are ignored.
+    ///
+    /// ```text
+    /// # blue to orange
+    /// hsv
+    /// 0.0  0.1 0.2 0.8
+    /// 1.0  1.0 0.6 0.1
+    /// ```
+    pub fn load(path: &Path) -> Result<Palette, PaletteError> {
+        let source = fs::read_to_string(path).map_err(|e| PaletteError::Io(path.to_path_buf(), e))?;
+        Self::parse(&source).map_err(|(line, message)| PaletteError::Parse(path.to_path_buf(), line, message))
+    }
+
+    /// Parses the palette file format, returning the line number and a message on failure.
+    fn parse(source: &str) -> Result<Palette, (usize, String)> {
+        let mut lines = source
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.split('#').next().unwrap().trim()))
+            .filter(|(_, line)| !line.is_empty());
+
+        let (kind_line, kind) = lines.next().ok_or((1, String::from("palette file is empty")))?;
+        let mut rows = Vec::new();
+        for (line_number, line) in lines {
+            let mut words = line.split_whitespace();
+            let key = if kind == "cosine" { words.next() } else { None };
+            let numbers = words
+                .map(|word| word.parse::<f64>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| (line_number, format!("{}", e)))?;
+            match numbers[..] {
+                [position, r, g, b] if key.is_none() => rows.push((line_number, None, [position, r, g, b])),
+                [r, g, b] if key.is_some() => rows.push((line_number, key, [0.0, r, g, b])),
+                _ if key.is_some() => return Err((line_number, String::from("expected a name followed by r g b"))),
+                _ => return Err((line_number, String::from("expected position r g b"))),
+            }
+        }
+
+        match kind {
+            "linear" | "hsv" => {
+                let stops = rows.iter().map(|&(_, _, stop)| Stop::from(stop)).collect();
+                let palette = if kind == "linear" { Palette::Linear(stops) } else { Palette::Hsv(stops) };
+                palette.validated().map_err(|e| (kind_line, e))
+            }
+            "cosine" => {
+                let unknown = rows.iter().find(|(_, key, _)| !matches!(key, Some("a" | "b" | "c" | "d")));
+                if let Some(&(line_number, Some(key), _)) = unknown {
+                    return Err((line_number, format!("unknown cosine coefficient '{}', expected a, b, c or d", key)));
+                }
+                let coefficient = |name: &str| {
+                    rows.iter()
+                        .find(|(_, key, _)| *key == Some(name))
+                        .map(|&(_, _, [_, r, g, b])| Vec3 { x: r, y: g, z: b })
+                        .ok_or((kind_line, format!("cosine palette is missing its {} line", name)))
+                };
+                Ok(Palette::Cosine {
+                    a: coefficient("a")?,
+                    b: coefficient("b")?,
+                    c: coefficient("c")?,
+                    d: coefficient("d")?,
+                })
+            }
+            _ => Err((kind_line, format!("unknown palette kind '{}', expected linear, hsv or cosine", kind))),
+        }
+    }
+
+    /// Checks the stops of a linear or HSV palette can be sampled.
+    fn validated(self) -> Result<Palette, String> {
+        if let Palette::Linear(stops) | Palette::Hsv(stops) = &self {
+            if stops.is_empty() {
+                return Err(String::from("palette needs at least one stop"));
+            }
+            if stops.windows(2).any(|pair| pair[0].position > pair[1].position) {
+                return Err(String::from("palette stops must be in order of position"));
+            }
+        }
+        Ok(self)
+    }
+}
+
+/// A preset name, or otherwise the path of a palette file relative to the working directory.
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Palette::named(s, Path::new(""))
+    }
+}
+
+/// A palette as a scene file gives it. Preset names and palette file paths are kept as they are written until
+/// `resolve` looks them up, so that paths can be relative to the scene file rather than the working directory.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "PaletteForm")]
+pub enum PaletteDesc {
+    Named(String),
+    Inline(Palette),
+}
+
+impl PaletteDesc {
+    /// The palette, reading palette files relative to `dir`.
+    pub fn resolve(&self, dir: &Path) -> Result<Palette, String> {
+        match self {
+            PaletteDesc::Named(name) => Palette::named(name, dir),
+            PaletteDesc::Inline(palette) => Ok(palette.clone()),
+        }
+    }
+}
+
+/// The forms a palette can take in a scene file: a preset name or palette file path, a list of evenly spaced
+/// colors, or a table of the palette's stops or coefficients.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PaletteForm {
+    Named(String),
+    Colors(Vec<Vec3>),
+    Table(PaletteTable),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum PaletteTable {
+    Linear { stops: Vec<Stop> },
+    Hsv { stops: Vec<Stop> },
+    Cosine { a: Vec3, b: Vec3, c: Vec3, d: Vec3 },
+}
+
+impl TryFrom<PaletteForm> for PaletteDesc {
+    type Error = String;
+
+    fn try_from(form: PaletteForm) -> Result<Self, Self::Error> {
+        let palette = match form {
+            PaletteForm::Named(name) => return Ok(PaletteDesc::Named(name)),
+            PaletteForm::Colors(colors) => {
+                let step = 1.0 / (colors.len().max(2) - 1) as f64;
+                let stops = colors
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, color)| Stop { position: i as f64 * step, color })
+                    .collect();
+                Palette::Linear(stops).validated()?
+            }
+            PaletteForm::Table(PaletteTable::Linear { stops }) => Palette::Linear(stops).validated()?,
+            PaletteForm::Table(PaletteTable::Hsv { stops }) => Palette::Hsv(stops).validated()?,
+            PaletteForm::Table(PaletteTable::Cosine { a, b, c, d }) => Palette::Cosine { a, b, c, d },
+        };
+        Ok(PaletteDesc::Inline(palette))
+    }
+}
+
+fn hsv_lerp(from: Vec3, to: Vec3, amount: f64) -> Vec3 {
+    let (from, to) = (rgb_to_hsv(from), rgb_to_hsv(to));
+    // go the short way around the hue circle
+    let mut hue_change = to.x - from.x;
+    if hue_change > 0.5 {
+        hue_change -= 1.0;
+    } else if hue_change < -0.5 {
+        hue_change += 1.0;
+    }
+    hsv_to_rgb(Vec3 {
+        x: (from.x + amount * hue_change).rem_euclid(1.0),
+        y: from.y + amount * (to.y - from.y),
+        z: from.z + amount * (to.z - from.z),
+    })
+}
+
+/// Hue, saturation and value, each from 0 to 1.
+fn rgb_to_hsv(rgb: Vec3) -> Vec3 {
+    let max = rgb.max_component();
+    let min = rgb.x.min(rgb.y).min(rgb.z);
+    let chroma = max - min;
+    let hue = if chroma == 0.0 {
+        0.0
+    } else if max == rgb.x {
+        ((rgb.y - rgb.z) / chroma).rem_euclid(6.0)
+    } else if max == rgb.y {
+        (rgb.z - rgb.x) / chroma + 2.0
+    } else {
+        (rgb.x - rgb.y) / chroma + 4.0
+    };
+    let saturation = if max == 0.0 { 0.0 } else { chroma / max };
+    Vec3 { x: hue / 6.0, y: saturation, z: max }
+}
+
+fn hsv_to_rgb(hsv: Vec3) -> Vec3 {
+    let sector = hsv.x * 6.0;
+    let chroma = hsv.z * hsv.y;
+    let second = chroma * (1.0 - (sector.rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match sector as u32 {
+        0 => (chroma, second, 0.0),
+        1 => (second, chroma, 0.0),
+        2 => (0.0, chroma, second),
+        3 => (0.0, second, chroma),
+        4 => (second, 0.0, chroma),
+        _ => (chroma, 0.0, second),
+    };
+    let min = hsv.z - chroma;
+    Vec3 { x: r + min, y: g + min, z: b + min }
+}
+
+#[derive(Debug)]
+pub enum PaletteError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, usize, String),
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PaletteError::Io(path, e) => write!(f, "could not read palette file {}: {}", path.display(), e),
+            PaletteError::Parse(path, line, message) => {
+                write!(f, "invalid palette file {}: {} at line {}", path.display(), message, line)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPS: f64 = 1E-9;
+
+    fn assert_color(actual: Vec3, expected: (f64, f64, f64)) {
+        let expected = Vec3::from(expected);
+        assert!((actual - expected).magnitude() < EPS, "color was {:?}, expected {:?}", actual, expected);
+    }
+
+    #[test]
+    fn linear_interpolates_between_stops() {
+        let palette = Palette::Linear(vec![
+            Stop { position: 0.0, color: (0.0, 0.0, 0.0).into() },
+            Stop { position: 0.5, color: (1.0, 0.0, 0.0).into() },
+            Stop { position: 1.0, color: (1.0, 1.0, 1.0).into() },
+        ]);
+        assert_color(palette.color(0.25), (0.5, 0.0, 0.0));
+        assert_color(palette.color(0.75), (1.0, 0.5, 0.5));
+        // clamped outside the stops
+        assert_color(palette.color(-1.0), (0.0, 0.0, 0.0));
+        assert_color(palette.color(2.0), (1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn hsv_stays_saturated() {
+        let palette = Palette::preset("rainbow").unwrap();
+        // halfway from red to green is yellow rather than the dark olive a linear blend gives
+        assert_color(palette.color(0.25), (1.0, 1.0, 0.0));
+        assert_color(palette.color(0.5), (0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn hsv_round_trip() {
+        for &rgb in &[(0.2, 0.4, 0.6), (0.9, 0.1, 0.5), (0.5, 0.5, 0.5), (0.0, 0.0, 0.0)] {
+            assert_color(hsv_to_rgb(rgb_to_hsv(rgb.into())), rgb);
+        }
+    }
+
+    #[test]
+    fn parse_palette_file() {
+        let palette = Palette::parse("# comment\nhsv\n\n0 1 0 0  # red\n1 0 0 1\n").unwrap();
+        assert_eq!(palette, Palette::Hsv(vec![
+            Stop { position: 0.0, color: (1.0, 0.0, 0.0).into() },
+            Stop { position: 1.0, color: (0.0, 0.0, 1.0).into() },
+        ]));
+
+        let palette = Palette::parse("cosine\na 0.5 0.5 0.5\nb 0.5 0.5 0.5\nc 1 1 1\nd 0 0.33 0.67").unwrap();
+        assert_eq!(palette, Palette::preset("cosine").unwrap());
+    }
+
+    #[test]
+    fn parse_errors_report_the_line() {
+        assert_eq!(Palette::parse("linear\n0 1 0 0\n1 0 x 1").unwrap_err().0, 3);
+        assert_eq!(Palette::parse("linear\n1 1 0 0\n0 0 0 1").unwrap_err().0, 1);
+        assert_eq!(Palette::parse("cosine\na 1 1 1").unwrap_err().0, 1);
+        assert_eq!(Palette::parse("cosine\na 1 1 1\nb 1 1 1\nx 1 1 1\nc 1 1 1\nd 1 1 1").unwrap_err().0, 4);
+        assert_eq!(Palette::parse("\n\nspline\n").unwrap_err().0, 3);
+    }
+
+    #[test]
+    fn paths_are_relative_to_the_scene() {
+        let dir = std::env::temp_dir().join(format!("marcher-palette-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("red.txt"), "linear\n0 1 0 0\n").unwrap();
+        let palette = PaletteDesc::Named(String::from("red.txt")).resolve(&dir);
+        // the command line's palettes are relative to the working directory
+        let from_command_line = "red.txt".parse::<Palette>();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(palette.unwrap(), Palette::Linear(vec![Stop { position: 0.0, color: (1.0, 0.0, 0.0).into() }]));
+        assert!(from_command_line.is_err());
+    }
+}
@@ -0,0 +1,94 @@
+use serde::de::{Error, Unexpected};
+use serde::{Deserialize, Deserializer};
+
+// Deserializers for scene file numbers that have to stay in the same range as their command line options. Use them
+// with `#[serde(deserialize_with = "...")]`, and the `optional_` versions for `Option` fields.
+
+fn bounded<'de, D: Deserializer<'de>>(
+    deserializer: D,
+    valid: impl Fn(f64) -> bool,
+    expected: &str) -> Result<f64, D::Error> {
+    let value = f64::deserialize(deserializer)?;
+    if valid(value) {
+        Ok(value)
+    } else {
+        Err(D::Error::invalid_value(Unexpected::Float(value), &expected))
+    }
+}
+
+pub fn non_negative<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    bounded(deserializer, |value| value >= 0.0, "a number of at least 0")
+}
+
+pub fn optional_non_negative<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+    non_negative(deserializer).map(Some)
+}
+
+pub fn unit_interval<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    bounded(deserializer, |value| (0.0..=1.0).contains(&value), "a number from 0 to 1")
+}
+
+pub fn positive<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    bounded(deserializer, |value| value > 0.0, "a number greater than 0")
+}
+
+pub fn optional_positive<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+    positive(deserializer).map(Some)
+}
+
+/// Counts, like samples or steps, that something has to happen at least once.
+pub fn optional_positive_int<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    let value = u32::deserialize(deserializer)?;
+    if value > 0 {
+        Ok(Some(value))
+    } else {
+        Err(D::Error::invalid_value(Unexpected::Unsigned(0), &"a whole number greater than 0"))
+    }
+}
+
+/// Powers of the mandelbulb formula, which only escapes to infinity, and only has a distance estimate, when the
+/// power is greater than 1.
+pub fn power<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    bounded(deserializer, |value| value > 1.0, "a power greater than 1")
+}
+
+/// Indices of refraction. Nothing slows light down less than a vacuum does, and a lower index would reflect all the
+/// light entering the object at a shallow enough angle.
+pub fn ior<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    bounded(deserializer, |value| value >= 1.0, "an index of refraction of at least 1")
+}
+
+/// Fields of view, which have to be narrower than a half turn to fit on a flat image.
+pub fn optional_fov<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+    bounded(deserializer, |value| value > 0.0 && value < 180.0, "an angle between 0 and 180 degrees").map(Some)
+}
+
+/// Fisheye angles, which show everything around the camera at a full turn.
+pub fn optional_fisheye_angle<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+    bounded(deserializer, |value| value > 0.0 && value <= 360.0, "an angle greater than 0 and up to 360 degrees")
+        .map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    #[derive(Debug, Default, Deserialize)]
+    #[serde(default)]
+    struct Counts {
+        #[serde(deserialize_with = "super::optional_positive")]
+        step: Option<f64>,
+        #[serde(deserialize_with = "super::optional_positive_int")]
+        samples: Option<u32>,
+    }
+
+    #[test]
+    fn positive_rejects_zero() {
+        let counts: Counts = toml::from_str("step = 0.5\nsamples = 4").unwrap();
+        assert_eq!((counts.step, counts.samples), (Some(0.5), Some(4)));
+        assert!(toml::from_str::<Counts>("").unwrap().samples.is_none());
+        assert!(toml::from_str::<Counts>("step = 0.0").is_err());
+        assert!(toml::from_str::<Counts>("step = -1.0").is_err());
+        assert!(toml::from_str::<Counts>("samples = 0").is_err());
+    }
+}
@@ -1,4 +1,6 @@
 use num_complex::Complex64;
+use std::sync::Mutex;
+use crate::palette::Palette;
 use crate::vec3::Vec3;
 
 static C: Complex64 = Complex64::new(-0.1, 0.651);
@@ -7,11 +9,11 @@ static MAX_JULIA_ITERS: i32 = 500;
 static MAX_RAYMARCH_ITERS: i32 = 500;
 static RAYMARCH_HIT_THRESHOLD: f64 = 0.01;
 
-static mut POINTS: Vec<Vec3> = Vec::new();
+static POINTS: Mutex<Vec<Vec3>> = Mutex::new(Vec::new());
 static RED: Vec3 = Vec3 { x: 1.0, y: 0.0, z: 0.0 };
 static BLUE: Vec3 = Vec3 { x: 0.0, y: 0.0, z: 1.0 };
 
-pub fn draw(frame: &mut [u32]) {
+pub fn draw(frame: &mut [u32], palette: &Palette) {
     // let mut C = Complex64::new(-0.1, 0.651);
 
     for (i, pix) in frame.iter_mut().enumerate() {
@@ -45,7 +47,7 @@ pub fn draw(frame: &mut [u32]) {
         let dist = if dist < 0.0 { 0.0 } else { dist };
         let dist = dist / 4.0;
         let logdist = -dist.ln();
-        *pix = Vec3::from(logdist / 20.0).into();
+        *pix = palette.color(logdist / 20.0).into();
         // *pix = Vec3::from_f64(count as f64 / max_iters as f64).into();
         // *pix = if logdist > 13.0 {Vec3::from_f64(1.0).into()} else {Vec3::from_f64(0.0).into()};
     }
@@ -71,11 +73,10 @@ pub fn dist_estimate(zx: f64, zy: f64) -> f64 {
     }
 
     let dist: f64 = z.norm() * z.norm().ln() / dz.norm();
-    let dist = if dist < 0.0 { 0.0 } else { dist };
-    dist
+    if dist < 0.0 { 0.0 } else { dist }
 }
 
-pub fn draw_ray2d(frame: &mut [u32], mouse_x: f64, mouse_y: f64) {
+pub fn draw_ray2d(frame: &mut [u32], mouse_x: f64, mouse_y: f64, palette: &Palette) {
     // draw set bg:
     for (i, pix) in frame.iter_mut().enumerate() {
         let x = i % super::WIDTH;
@@ -86,15 +87,15 @@ pub fn draw_ray2d(frame: &mut [u32], mouse_x: f64, mouse_y: f64) {
 
         let dist = dist_estimate(x, y);
         let logdist = -dist.ln();
-        *pix = Vec3::from(logdist / 15.0).into();
-        *pix = Vec3::from(dist).into();
+        *pix = palette.color(logdist / 15.0).into();
+        *pix = palette.color(dist).into();
     }
 
     //march rays
     let start_point = Vec3::from((0, super::WIDTH as i32 / 2, 0));
     let dir = (Vec3::from((mouse_x, mouse_y, 0.0)) - start_point).normalized();
 
-    let mut current_point = start_point.clone();
+    let mut current_point = start_point;
     let mut t = 0.0;
     let mut iters = 0;
 
@@ -113,14 +114,13 @@ pub fn draw_ray2d(frame: &mut [u32], mouse_x: f64, mouse_y: f64) {
         }
     };
 
-    unsafe {
-        if was_hit {
-            POINTS.push(current_point);
-        }
+    let mut points = POINTS.lock().unwrap();
+    if was_hit {
+        points.push(current_point);
+    }
 
-        for p in POINTS.iter() {
-            draw_point(frame, p.x, p.y, BLUE);
-        }
+    for p in points.iter() {
+        draw_point(frame, p.x, p.y, BLUE);
     }
 }
 
@@ -1,13 +1,17 @@
 use minifb::{Key, Window, WindowOptions, MouseMode};
 use cgmath::Quaternion;
+use crate::palette::Palette;
 
+// both modules keep their alternate draw/debug routines around for experimenting
+#[allow(dead_code)]
 mod julia_distance;
+#[allow(dead_code)]
 mod quaternion_julia;
 
 const WIDTH: usize = 1024;
 const HEIGHT: usize = 1024;
 
-pub fn main(c: Quaternion<f64>) {
+pub fn main(c: Quaternion<f64>, palette: Palette) {
     let mut buffer: Vec<u32> = vec![0; WIDTH * HEIGHT];
 
     let mut window = Window::new(
@@ -30,8 +34,14 @@ pub fn main(c: Quaternion<f64>) {
             mouse_absolute.1 / window_size.1 as f32 * HEIGHT as f32
         );
 
-        // julia_distance::draw_ray2d(buffer.as_mut_slice(), mouse_scaled.0 as f64, mouse_scaled.1 as f64);
-        quaternion_julia::draw_quaternion_julia(buffer.as_mut_slice(), mouse_scaled.0 as f64, mouse_scaled.1 as f64, c);
+        // julia_distance::draw_ray2d(buffer.as_mut_slice(), mouse_scaled.0 as f64, mouse_scaled.1 as f64, &palette);
+        quaternion_julia::draw_quaternion_julia(
+            buffer.as_mut_slice(),
+            mouse_scaled.0 as f64,
+            mouse_scaled.1 as f64,
+            c,
+            &palette,
+        );
         window.update_with_buffer(&buffer, WIDTH, HEIGHT).unwrap();
     }
 }
@@ -1,3 +1,4 @@
+use crate::palette::Palette;
 use crate::vec3::Vec3;
 use cgmath::{Quaternion, InnerSpace, Zero};
 
@@ -59,8 +60,7 @@ fn get_val_dist_estimate(z: Quaternion64) -> f64 {
     let dist = if dist < 0.0 { 0.0 } else { dist };
     // let dist = dist.abs();
     // dbg!(dist);
-    let log_dist = dist.ln();
-    log_dist;
+    // let log_dist = dist.ln();
     dist
 }
 
@@ -82,7 +82,7 @@ fn get_val_mandelbrot(pos: Quaternion64) -> f64 {
     count as f64 / MAX_ITERATIONS as f64
 }
 
-pub fn draw_quaternion_julia(frame: &mut [u32], mouse_x: f64, mouse_y: f64, c: Quaternion64) {
+pub fn draw_quaternion_julia(frame: &mut [u32], mouse_x: f64, mouse_y: f64, c: Quaternion64, palette: &Palette) {
     for (i, pix) in frame.iter_mut().enumerate() {
         let x = i % super::WIDTH;
         let y = i / super::WIDTH;
@@ -94,18 +94,15 @@ pub fn draw_quaternion_julia(frame: &mut [u32], mouse_x: f64, mouse_y: f64, c: Q
         let mouse_plane_y = mouse_y / super::HEIGHT as f64;
         // let val = distance_to2((x, y, 0.0).into());
         let val = get_val(Quaternion64::new(x, y, mouse_plane_x, mouse_plane_y), c);
-        *pix = Vec3::from(val).into();
+        *pix = palette.color(val).into();
     }
 
     //trace_ray(frame, mouse_x, mouse_y);
 }
 
 fn trace_ray(frame: &mut [u32], mouse_x: f64, mouse_y: f64) {
-    let mouse_plane_x = mouse_x / super::WIDTH as f64;
-    let mouse_plane_y = mouse_y / super::HEIGHT as f64;
-
     let start_point = Vec3::from((4, 4, 0));
-    let mut current_point = start_point.clone();
+    let mut current_point = start_point;
     let dir = (Vec3::from((s2c(mouse_x), s2c(mouse_y), 0.0)) - start_point).normalized();
     let mut t = 0.0;
     let mut iters = 0;
@@ -0,0 +1,402 @@
+use crate::vec3::Vec3;
+use serde::Deserialize;
+use std::f64::consts::PI;
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Projection {
+    /// Rays spread out from the camera position, so distant things look smaller
+    Perspective,
+    /// Rays run parallel to the view direction from a rectangle around the camera position, so things keep their
+    /// size however far away they are
+    Orthographic,
+    /// Every direction around the camera, with longitude across the image and latitude up it. The view direction is
+    /// in the middle, and the image should be twice as wide as it is high
+    Equirectangular,
+    /// Equidistant fisheye: the angle from the view direction grows evenly with the distance from the middle of the
+    /// image, out to half the fisheye angle at the edge of a circle filling the image's shorter side
+    Fisheye,
+    /// The six 90 degree views along and against the view, right and up directions
+    Cube,
+}
+
+/// How the six faces of a cube map are laid out.
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CubeLayout {
+    /// All the faces in one image, four faces wide and three high, unfolded around the front face:
+    ///
+    /// ```text
+    ///       top
+    /// left  front right back
+    ///       bottom
+    /// ```
+    Cross,
+    /// Each face in its own square image, named after the face
+    Faces,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CubeFace {
+    Front,
+    Back,
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+impl CubeFace {
+    pub const ALL: [CubeFace; 6] =
+        [CubeFace::Front, CubeFace::Back, CubeFace::Left, CubeFace::Right, CubeFace::Top, CubeFace::Bottom];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CubeFace::Front => "front",
+            CubeFace::Back => "back",
+            CubeFace::Left => "left",
+            CubeFace::Right => "right",
+            CubeFace::Top => "top",
+            CubeFace::Bottom => "bottom",
+        }
+    }
+
+    /// Direction through the point `(a, b)` of the face, each from -1 to 1 with `a` rightwards and `b` upwards. The
+    /// faces are oriented so their edges line up in the cross layout.
+    fn direction(self, basis: &CameraBasis, a: f64, b: f64) -> Vec3 {
+        let CameraBasis { forward, right, up } = *basis;
+        match self {
+            CubeFace::Front => forward + a * right + b * up,
+            CubeFace::Back => -forward - a * right + b * up,
+            CubeFace::Left => -right + a * forward + b * up,
+            CubeFace::Right => right - a * forward + b * up,
+            CubeFace::Top => up + a * right - b * forward,
+            CubeFace::Bottom => -up + a * right + b * forward,
+        }
+    }
+
+    /// Face at a cell of the cross layout, by column and row.
+    fn in_cross(column: usize, row: usize) -> Option<CubeFace> {
+        match (column, row) {
+            (1, 0) => Some(CubeFace::Top),
+            (0, 1) => Some(CubeFace::Left),
+            (1, 1) => Some(CubeFace::Front),
+            (2, 1) => Some(CubeFace::Right),
+            (3, 1) => Some(CubeFace::Back),
+            (1, 2) => Some(CubeFace::Bottom),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Camera {
+    pub position: Vec3,
+    pub look_at: Vec3,
+    /// Direction that appears upwards in the image. It only has to point roughly up: the part of it along the view
+    /// direction is ignored
+    pub up: Vec3,
+    /// Rotation of the camera about the view direction, in degrees. Positive values tip the top of the image to the
+    /// left, turning the scene clockwise in the image
+    pub roll: f64,
+    pub projection: Projection,
+    /// Vertical field of view of the perspective projection, in degrees
+    pub fov: f64,
+    /// Width of the area the orthographic projection shows, in scene units
+    pub view_width: f64,
+    /// Field of view across the image circle of the fisheye projection, in degrees. 180 shows a hemisphere
+    pub fisheye_angle: f64,
+    pub cube_layout: CubeLayout,
+    /// The single face to render when the cube map's faces are rendered as separate images. `None` renders the
+    /// cross layout
+    pub cube_face: Option<CubeFace>,
+    /// Radius of the lens. Anything away from the focus distance is blurred, more so the larger the lens. 0 keeps
+    /// everything sharp. Only the perspective and orthographic projections have depth of field
+    pub aperture: f64,
+    /// Distance along the view direction to the plane that is in focus. Defaults to the distance to `look_at`
+    pub focus_distance: Option<f64>,
+    /// Sideways shift of the perspective projection's image, in units of half the image's height, without turning the
+    /// camera. Used for the eyes of a stereo pair, which both look ahead and shift their images towards each other
+    pub image_shift: f64,
+}
+
+/// Unit vectors along the view direction and the directions that appear rightwards and upwards in the image.
+#[derive(Copy, Clone)]
+pub struct CameraBasis {
+    pub forward: Vec3,
+    pub right: Vec3,
+    pub up: Vec3,
+}
+
+impl Camera {
+    pub fn basis(&self) -> CameraBasis {
+        let forward = (self.look_at - self.position).normalized();
+        let mut right = forward.cross(self.up);
+        if right.magnitude() < 1E-9 {
+            // looking straight along the up vector leaves the image's rotation undefined, so pick any sideways
+            // direction rather than dividing by zero
+            let axis = if forward.x.abs() < 0.9 { Vec3::from((1, 0, 0)) } else { Vec3::from((0, 0, 1)) };
+            right = axis.cross(forward);
+        }
+        let right = right.normalized();
+        let up = right.cross(forward);
+
+        let (sin, cos) = self.roll.to_radians().sin_cos();
+        CameraBasis {
+            forward,
+            right: cos * right + sin * up,
+            up: cos * up - sin * right,
+        }
+    }
+
+    /// Origin and direction of the ray through the point `(x, y)` of the image, in pixels from its top left corner.
+    /// `None` for the parts of the image outside the projection, ie. around a fisheye's image circle and the empty
+    /// cells of a cube map cross.
+    pub fn ray(&self, x: f64, y: f64, (width, height): (usize, usize)) -> Option<(Vec3, Vec3)> {
+        let basis = self.basis();
+        // the image spans -1 to 1 vertically, and as far horizontally as its aspect ratio needs
+        let aspect = width as f64 / height as f64;
+        let u = (x / width as f64 * 2.0 - 1.0) * aspect;
+        let v = 1.0 - y / height as f64 * 2.0;
+
+        let dir = match self.projection {
+            Projection::Perspective => {
+                let scale = (0.5 * self.fov).to_radians().tan();
+                basis.forward + ((u + self.image_shift) * scale) * basis.right + (v * scale) * basis.up
+            }
+            Projection::Orthographic => {
+                let scale = 0.5 * self.view_width / aspect;
+                let origin = self.position + (u * scale) * basis.right + (v * scale) * basis.up;
+                return Some((origin, basis.forward));
+            }
+            Projection::Equirectangular => {
+                let longitude = PI * (x / width as f64 * 2.0 - 1.0);
+                let latitude = 0.5 * PI * v;
+                let (sin_lon, cos_lon) = longitude.sin_cos();
+                let (sin_lat, cos_lat) = latitude.sin_cos();
+                cos_lat * (sin_lon * basis.right + cos_lon * basis.forward) + sin_lat * basis.up
+            }
+            Projection::Fisheye => {
+                // the image circle fits the shorter side of the image
+                let scale = aspect.min(1.0);
+                let (u, v) = (u / scale, v / scale);
+                let radius = (u * u + v * v).sqrt();
+                if radius > 1.0 {
+                    return None;
+                }
+                let angle = radius * 0.5 * self.fisheye_angle.to_radians();
+                let around = v.atan2(u);
+                angle.cos() * basis.forward + angle.sin() * (around.cos() * basis.right + around.sin() * basis.up)
+            }
+            Projection::Cube => {
+                let (face, (a, b)) = match self.cube_face {
+                    Some(face) => (face, (x / width as f64 * 2.0 - 1.0, v)),
+                    None => {
+                        let cell_x = x / width as f64 * 4.0;
+                        let cell_y = y / height as f64 * 3.0;
+                        let face = CubeFace::in_cross(cell_x as usize, cell_y as usize)?;
+                        (face, (cell_x.fract() * 2.0 - 1.0, 1.0 - cell_y.fract() * 2.0))
+                    }
+                };
+                face.direction(&basis, a, b)
+            }
+        };
+        Some((self.position, dir.normalized()))
+    }
+
+    /// Like `ray`, but starting from a point on the lens rather than its center, for depth of field. `lens_sample`
+    /// picks the point, and should be uniformly distributed over [0, 1) in each component.
+    pub fn lens_ray(&self, x: f64, y: f64, size: (usize, usize), lens_sample: (f64, f64)) -> Option<(Vec3, Vec3)> {
+        let (origin, dir) = self.ray(x, y, size)?;
+        let has_focus_plane = self.projection == Projection::Perspective || self.projection == Projection::Orthographic;
+        if self.aperture <= 0.0 || !has_focus_plane {
+            return Some((origin, dir));
+        }
+
+        let basis = self.basis();
+        let focus_distance = self.focus_distance.unwrap_or_else(|| (self.look_at - self.position).magnitude());
+        // every ray through the lens for this pixel meets the pinhole ray on the focus plane
+        let focus_point = origin + (focus_distance / dir.dot(basis.forward)) * dir;
+        // square root so the points are spread evenly over the disk, rather than bunched up in the middle
+        let radius = self.aperture * lens_sample.0.sqrt();
+        let angle = 2.0 * PI * lens_sample.1;
+        let lens_point = origin + (radius * angle.cos()) * basis.right + (radius * angle.sin()) * basis.up;
+        Some((lens_point, (focus_point - lens_point).normalized()))
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            position: Vec3 { x: 2.0, y: 4.0, z: 4.0 },
+            look_at: Vec3 { x: 0.0, y: 0.0, z: 0.0 },
+            up: Vec3 { x: 0.0, y: 1.0, z: 0.0 },
+            roll: 0.0,
+            projection: Projection::Perspective,
+            fov: 90.0,
+            view_width: 4.0,
+            fisheye_angle: 180.0,
+            cube_layout: CubeLayout::Cross,
+            cube_face: None,
+            aperture: 0.0,
+            focus_distance: None,
+            image_shift: 0.0,
+        }
+    }
+}
+
+impl FromStr for Projection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "perspective" => Ok(Projection::Perspective),
+            "orthographic" => Ok(Projection::Orthographic),
+            "equirectangular" => Ok(Projection::Equirectangular),
+            "fisheye" => Ok(Projection::Fisheye),
+            "cube" => Ok(Projection::Cube),
+            _ => Err(format!("unknown projection {}", s)),
+        }
+    }
+}
+
+impl FromStr for CubeLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cross" => Ok(CubeLayout::Cross),
+            "faces" => Ok(CubeLayout::Faces),
+            _ => Err(format!("unknown cube map layout {}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPS: f64 = 1E-9;
+
+    fn camera(position: (i32, i32, i32), projection: Projection) -> Camera {
+        Camera {
+            position: position.into(),
+            look_at: Vec3::default(),
+            projection,
+            ..Camera::default()
+        }
+    }
+
+    #[test]
+    fn image_is_not_mirrored() {
+        // looking down -z with y up, the right of the image is +x and the top is +y
+        let camera = camera((0, 0, 5), Projection::Perspective);
+        let (_, right) = camera.ray(100.0, 50.0, (100, 100)).unwrap();
+        let (_, top) = camera.ray(50.0, 0.0, (100, 100)).unwrap();
+        assert!(right.x > 0.0 && right.y.abs() < EPS);
+        assert!(top.y > 0.0 && top.x.abs() < EPS);
+    }
+
+    #[test]
+    fn fov_is_vertical_and_aspect_is_kept() {
+        let camera = Camera { fov: 60.0, ..camera((0, 0, 5), Projection::Perspective) };
+        let (_, top) = camera.ray(100.0, 0.0, (200, 100)).unwrap();
+        let (_, right) = camera.ray(200.0, 50.0, (200, 100)).unwrap();
+        assert!((top.y.atan2(-top.z).to_degrees() - 30.0).abs() < 1E-6);
+        // twice as wide as it is high, so the horizontal extent is double the vertical one in the image plane
+        assert!((right.x / -right.z - 2.0 * top.y / -top.z).abs() < EPS);
+    }
+
+    #[test]
+    fn looking_straight_down() {
+        let camera = camera((0, 5, 0), Projection::Perspective);
+        let (_, center) = camera.ray(50.0, 50.0, (100, 100)).unwrap();
+        assert!((center - Vec3::from((0, -1, 0))).magnitude() < EPS);
+        let basis = camera.basis();
+        assert!((basis.right.magnitude() - 1.0).abs() < EPS);
+    }
+
+    #[test]
+    fn roll_rotates_the_image() {
+        let camera = Camera { roll: 90.0, ..camera((0, 0, 5), Projection::Perspective) };
+        // rolled a quarter turn, the top of the image looks towards -x
+        let (_, top) = camera.ray(50.0, 0.0, (100, 100)).unwrap();
+        assert!(top.x < 0.0 && top.y.abs() < EPS);
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel() {
+        let camera = Camera { view_width: 4.0, ..camera((0, 0, 5), Projection::Orthographic) };
+        let (left_origin, left_dir) = camera.ray(0.0, 50.0, (200, 100)).unwrap();
+        let (top_origin, top_dir) = camera.ray(100.0, 0.0, (200, 100)).unwrap();
+        assert_eq!(left_dir, top_dir);
+        assert!((left_origin - Vec3::from((-2, 0, 5))).magnitude() < EPS);
+        assert!((top_origin - Vec3::from((0, 1, 5))).magnitude() < EPS);
+    }
+
+    #[test]
+    fn lens_rays_meet_on_the_focus_plane() {
+        let camera = Camera { aperture: 0.5, focus_distance: Some(3.0), ..camera((0, 0, 5), Projection::Perspective) };
+        let (_, pinhole_dir) = camera.ray(70.0, 20.0, (100, 100)).unwrap();
+        let focus_point = Vec3::from((0, 0, 5)) + (3.0 / -pinhole_dir.z) * pinhole_dir;
+        for &sample in &[(0.0, 0.0), (0.5, 0.25), (0.99, 0.7)] {
+            let (origin, dir) = camera.lens_ray(70.0, 20.0, (100, 100), sample).unwrap();
+            assert!((origin - Vec3::from((0, 0, 5))).magnitude() <= 0.5 + EPS);
+            let along = (focus_point - origin).dot(dir);
+            assert!((origin + along * dir - focus_point).magnitude() < 1E-6);
+        }
+    }
+
+    #[test]
+    fn equirectangular_covers_every_direction() {
+        let camera = camera((0, 0, 5), Projection::Equirectangular);
+        let (_, center) = camera.ray(100.0, 50.0, (200, 100)).unwrap();
+        let (_, quarter) = camera.ray(150.0, 50.0, (200, 100)).unwrap();
+        let (_, edge) = camera.ray(0.0, 50.0, (200, 100)).unwrap();
+        let (_, top) = camera.ray(30.0, 0.0, (200, 100)).unwrap();
+        assert!((center - Vec3::from((0, 0, -1))).magnitude() < EPS);
+        assert!((quarter - Vec3::from((1, 0, 0))).magnitude() < EPS);
+        assert!((edge - Vec3::from((0, 0, 1))).magnitude() < EPS);
+        assert!((top - Vec3::from((0, 1, 0))).magnitude() < EPS);
+    }
+
+    #[test]
+    fn fisheye_angle_reaches_the_image_circle() {
+        let camera = Camera { fisheye_angle: 240.0, ..camera((0, 0, 5), Projection::Fisheye) };
+        // the circle fits the height of a wide image, so the corners and sides are outside it
+        assert!(camera.ray(0.0, 0.0, (200, 100)).is_none());
+        assert!(camera.ray(20.0, 50.0, (200, 100)).is_none());
+        let (_, top) = camera.ray(100.0, 0.0, (200, 100)).unwrap();
+        let (_, right) = camera.ray(150.0, 50.0, (200, 100)).unwrap();
+        assert!((top.dot(Vec3::from((0, 0, -1))).acos().to_degrees() - 120.0).abs() < 1E-6);
+        assert!(top.y > 0.0 && top.x.abs() < EPS);
+        assert!((right.dot(Vec3::from((0, 0, -1))).acos().to_degrees() - 120.0).abs() < 1E-6);
+        assert!(right.x > 0.0);
+    }
+
+    #[test]
+    fn cube_cross_faces_meet_at_their_edges() {
+        let camera = camera((0, 0, 5), Projection::Cube);
+        let size = (400, 300);
+        // either side of each boundary between neighbouring cells of the cross looks the same way
+        let boundaries = [
+            ((100.0, 150.0), (-1.0, 0.0)),
+            ((200.0, 150.0), (-1.0, 0.0)),
+            ((300.0, 150.0), (-1.0, 0.0)),
+            ((150.0, 100.0), (0.0, -1.0)),
+            ((150.0, 200.0), (0.0, -1.0)),
+        ];
+        for &((x, y), (dx, dy)) in &boundaries {
+            let (_, after) = camera.ray(x, y, size).unwrap();
+            let (_, before) = camera.ray(x + 1E-9 * dx, y + 1E-9 * dy, size).unwrap();
+            assert!((after - before).magnitude() < 1E-6, "faces don't meet at {}, {}", x, y);
+        }
+        assert!(camera.ray(50.0, 50.0, size).is_none());
+        let (_, front) = camera.ray(150.0, 150.0, size).unwrap();
+        assert!((front - Vec3::from((0, 0, -1))).magnitude() < EPS);
+        let single = Camera { cube_face: Some(CubeFace::Right), ..camera };
+        let (_, right) = single.ray(50.0, 50.0, (100, 100)).unwrap();
+        assert!((right - Vec3::from((1, 0, 0))).magnitude() < EPS);
+    }
+}
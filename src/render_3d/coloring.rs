@@ -0,0 +1,82 @@
+use super::material::{Material, MaterialOverrides};
+use super::scene_object::SceneObject;
+use crate::palette::Palette;
+use crate::vec3::Vec3;
+use serde::Deserialize;
+
+// Wrappers that leave an object's shape alone and only change the color of its surface.
+
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GradientSource {
+    /// How far along the line from `start` to `end` the surface point is
+    Position,
+    /// How closely the surface normal points along the direction from `start` to `end`, eg. to color the upward
+    /// facing parts of an object differently
+    Normal,
+}
+
+/// Colors an object with a palette, sampled from its start at `start` to its end at `end`. Only the albedo of the
+/// object's material is replaced.
+pub struct Gradient<O: SceneObject> {
+    pub object: O,
+    pub source: GradientSource,
+    pub start: Vec3,
+    pub end: Vec3,
+    pub palette: Palette,
+}
+
+impl<O: SceneObject> Gradient<O> {
+    /// Position along the gradient, from 0 at the start of the palette to 1 at its end.
+    fn amount(&self, point: Vec3, normal: Vec3) -> f64 {
+        let axis = self.end - self.start;
+        match self.source {
+            GradientSource::Position => ((point - self.start).dot(axis) / axis.dot(axis)).clamp(0.0, 1.0),
+            GradientSource::Normal => 0.5 + 0.5 * normal.dot(axis.normalized()),
+        }
+    }
+}
+
+impl<O: SceneObject> SceneObject for Gradient<O> {
+    fn distance_to(&self, point: Vec3, t: f64) -> f64 {
+        self.object.distance_to(point, t)
+    }
+
+    fn get_material(&self, t: f64) -> Material {
+        Material { albedo: self.palette.color(0.0), ..self.object.get_material(t) }
+    }
+
+    fn material_at(&self, point: Vec3, normal: Vec3, t: f64) -> Material {
+        let material = self.object.material_at(point, normal, t);
+        Material { albedo: self.palette.color(self.amount(point, normal)), ..material }
+    }
+
+    fn normal(&self, p: Vec3, t: f64) -> Vec3 {
+        self.object.normal(p, t)
+    }
+}
+
+/// An object with properties of all of its materials replaced, eg. by material options given on the command line for
+/// a scene file's object.
+pub struct Overridden<O: SceneObject> {
+    pub object: O,
+    pub overrides: MaterialOverrides,
+}
+
+impl<O: SceneObject> SceneObject for Overridden<O> {
+    fn distance_to(&self, point: Vec3, t: f64) -> f64 {
+        self.object.distance_to(point, t)
+    }
+
+    fn get_material(&self, t: f64) -> Material {
+        self.overrides.apply(self.object.get_material(t))
+    }
+
+    fn material_at(&self, point: Vec3, normal: Vec3, t: f64) -> Material {
+        self.overrides.apply(self.object.material_at(point, normal, t))
+    }
+
+    fn normal(&self, p: Vec3, t: f64) -> Vec3 {
+        self.object.normal(p, t)
+    }
+}
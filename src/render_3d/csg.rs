@@ -0,0 +1,268 @@
+use super::material::Material;
+use super::scene_object::SceneObject;
+use crate::vec3::Vec3;
+
+// Constructive solid geometry. The hard combinators take the material of whichever operand's surface is the closest,
+// the smooth ones blend distance and material over a region `radius` wide where the two surfaces meet.
+
+pub struct Union<A: SceneObject, B: SceneObject> {
+    pub a: A,
+    pub b: B,
+}
+
+impl<A: SceneObject, B: SceneObject> SceneObject for Union<A, B> {
+    fn distance_to(&self, point: Vec3, t: f64) -> f64 {
+        self.a.distance_to(point, t).min(self.b.distance_to(point, t))
+    }
+
+    fn get_material(&self, t: f64) -> Material {
+        self.a.get_material(t)
+    }
+
+    fn material_at(&self, point: Vec3, normal: Vec3, t: f64) -> Material {
+        if self.a.distance_to(point, t) <= self.b.distance_to(point, t) {
+            self.a.material_at(point, normal, t)
+        } else {
+            self.b.material_at(point, normal, t)
+        }
+    }
+}
+
+pub struct Intersection<A: SceneObject, B: SceneObject> {
+    pub a: A,
+    pub b: B,
+}
+
+impl<A: SceneObject, B: SceneObject> SceneObject for Intersection<A, B> {
+    fn distance_to(&self, point: Vec3, t: f64) -> f64 {
+        self.a.distance_to(point, t).max(self.b.distance_to(point, t))
+    }
+
+    fn get_material(&self, t: f64) -> Material {
+        self.a.get_material(t)
+    }
+
+    fn material_at(&self, point: Vec3, normal: Vec3, t: f64) -> Material {
+        if self.a.distance_to(point, t) >= self.b.distance_to(point, t) {
+            self.a.material_at(point, normal, t)
+        } else {
+            self.b.material_at(point, normal, t)
+        }
+    }
+}
+
+/// `a` with `b` cut out of it. The cut surface takes the material of `b`.
+pub struct Difference<A: SceneObject, B: SceneObject> {
+    pub a: A,
+    pub b: B,
+}
+
+impl<A: SceneObject, B: SceneObject> SceneObject for Difference<A, B> {
+    fn distance_to(&self, point: Vec3, t: f64) -> f64 {
+        self.a.distance_to(point, t).max(-self.b.distance_to(point, t))
+    }
+
+    fn get_material(&self, t: f64) -> Material {
+        self.a.get_material(t)
+    }
+
+    fn material_at(&self, point: Vec3, normal: Vec3, t: f64) -> Material {
+        if self.a.distance_to(point, t) >= -self.b.distance_to(point, t) {
+            self.a.material_at(point, normal, t)
+        } else {
+            self.b.material_at(point, normal, t)
+        }
+    }
+}
+
+pub struct SmoothUnion<A: SceneObject, B: SceneObject> {
+    pub a: A,
+    pub b: B,
+    pub radius: f64,
+}
+
+impl<A: SceneObject, B: SceneObject> SmoothUnion<A, B> {
+    /// Blend factor: 1 where only `a` contributes, 0 where only `b` does.
+    fn blend(&self, dist_a: f64, dist_b: f64) -> f64 {
+        (0.5 + 0.5 * (dist_b - dist_a) / self.radius).clamp(0.0, 1.0)
+    }
+}
+
+impl<A: SceneObject, B: SceneObject> SceneObject for SmoothUnion<A, B> {
+    fn distance_to(&self, point: Vec3, t: f64) -> f64 {
+        let dist_a = self.a.distance_to(point, t);
+        let dist_b = self.b.distance_to(point, t);
+        let h = self.blend(dist_a, dist_b);
+        mix(dist_b, dist_a, h) - self.radius * h * (1.0 - h)
+    }
+
+    fn get_material(&self, t: f64) -> Material {
+        self.a.get_material(t)
+    }
+
+    fn material_at(&self, point: Vec3, normal: Vec3, t: f64) -> Material {
+        let h = self.blend(self.a.distance_to(point, t), self.b.distance_to(point, t));
+        self.b.material_at(point, normal, t).lerp(&self.a.material_at(point, normal, t), h)
+    }
+}
+
+pub struct SmoothIntersection<A: SceneObject, B: SceneObject> {
+    pub a: A,
+    pub b: B,
+    pub radius: f64,
+}
+
+impl<A: SceneObject, B: SceneObject> SmoothIntersection<A, B> {
+    /// Blend factor: 1 where only `a` contributes, 0 where only `b` does.
+    fn blend(&self, dist_a: f64, dist_b: f64) -> f64 {
+        (0.5 - 0.5 * (dist_b - dist_a) / self.radius).clamp(0.0, 1.0)
+    }
+}
+
+impl<A: SceneObject, B: SceneObject> SceneObject for SmoothIntersection<A, B> {
+    fn distance_to(&self, point: Vec3, t: f64) -> f64 {
+        let dist_a = self.a.distance_to(point, t);
+        let dist_b = self.b.distance_to(point, t);
+        let h = self.blend(dist_a, dist_b);
+        mix(dist_b, dist_a, h) + self.radius * h * (1.0 - h)
+    }
+
+    fn get_material(&self, t: f64) -> Material {
+        self.a.get_material(t)
+    }
+
+    fn material_at(&self, point: Vec3, normal: Vec3, t: f64) -> Material {
+        let h = self.blend(self.a.distance_to(point, t), self.b.distance_to(point, t));
+        self.b.material_at(point, normal, t).lerp(&self.a.material_at(point, normal, t), h)
+    }
+}
+
+pub struct SmoothDifference<A: SceneObject, B: SceneObject> {
+    pub a: A,
+    pub b: B,
+    pub radius: f64,
+}
+
+impl<A: SceneObject, B: SceneObject> SmoothDifference<A, B> {
+    /// Blend factor: 1 where only `a` contributes, 0 where only the cut made by `b` does.
+    fn blend(&self, dist_a: f64, dist_b: f64) -> f64 {
+        (0.5 + 0.5 * (dist_a + dist_b) / self.radius).clamp(0.0, 1.0)
+    }
+}
+
+impl<A: SceneObject, B: SceneObject> SceneObject for SmoothDifference<A, B> {
+    fn distance_to(&self, point: Vec3, t: f64) -> f64 {
+        let dist_a = self.a.distance_to(point, t);
+        let dist_b = self.b.distance_to(point, t);
+        let h = self.blend(dist_a, dist_b);
+        mix(-dist_b, dist_a, h) + self.radius * h * (1.0 - h)
+    }
+
+    fn get_material(&self, t: f64) -> Material {
+        self.a.get_material(t)
+    }
+
+    fn material_at(&self, point: Vec3, normal: Vec3, t: f64) -> Material {
+        let h = self.blend(self.a.distance_to(point, t), self.b.distance_to(point, t));
+        self.b.material_at(point, normal, t).lerp(&self.a.material_at(point, normal, t), h)
+    }
+}
+
+fn mix(a: f64, b: f64, h: f64) -> f64 {
+    a + h * (b - a)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_3d::scene_object::Sphere;
+
+    const EPS: f64 = 1E-12;
+
+    /// Overlapping spheres either side of the origin along x, `a` red and `b` blue.
+    fn spheres() -> (Sphere, Sphere) {
+        let sphere = |x: f64, albedo: Vec3| Sphere {
+            center: (x, 0.0, 0.0).into(),
+            radius: 1.5,
+            material: Material { albedo, ..Material::default() },
+        };
+        (sphere(-1.0, (1, 0, 0).into()), sphere(1.0, (0, 0, 1).into()))
+    }
+
+    fn assert_distance<O: SceneObject>(object: &O, point: (f64, f64, f64), expected: f64) {
+        let dist = object.distance_to(point.into(), 0.0);
+        assert!((dist - expected).abs() < EPS, "distance at {:?} was {}, expected {}", point, dist, expected);
+    }
+
+    fn albedo_at<O: SceneObject>(object: &O, point: (f64, f64, f64)) -> Vec3 {
+        object.material_at(point.into(), (0, 1, 0).into(), 0.0).albedo
+    }
+
+    #[test]
+    fn union_takes_the_nearest_operand() {
+        let (a, b) = spheres();
+        let union = Union { a, b };
+        assert_distance(&union, (-3.0, 0.0, 0.0), 0.5);
+        assert_distance(&union, (0.0, 0.0, 0.0), -0.5);
+        assert_eq!(albedo_at(&union, (-3.0, 0.0, 0.0)), (1, 0, 0).into());
+        assert_eq!(albedo_at(&union, (3.0, 0.0, 0.0)), (0, 0, 1).into());
+    }
+
+    #[test]
+    fn intersection_takes_the_furthest_operand() {
+        let (a, b) = spheres();
+        let intersection = Intersection { a, b };
+        assert_distance(&intersection, (0.0, 0.0, 0.0), -0.5);
+        // past the right end of the lens the surface is the left sphere's
+        assert_distance(&intersection, (2.0, 0.0, 0.0), 1.5);
+        assert_eq!(albedo_at(&intersection, (2.0, 0.0, 0.0)), (1, 0, 0).into());
+        assert_eq!(albedo_at(&intersection, (-2.0, 0.0, 0.0)), (0, 0, 1).into());
+    }
+
+    #[test]
+    fn difference_cut_takes_the_material_of_b() {
+        let (a, b) = spheres();
+        let difference = Difference { a, b };
+        assert_distance(&difference, (-2.0, 0.0, 0.0), -0.5);
+        // inside the cut, half a unit from the surface of b
+        assert_distance(&difference, (0.0, 0.0, 0.0), 0.5);
+        assert_eq!(albedo_at(&difference, (-3.0, 0.0, 0.0)), (1, 0, 0).into());
+        assert_eq!(albedo_at(&difference, (0.0, 0.0, 0.0)), (0, 0, 1).into());
+    }
+
+    #[test]
+    fn smooth_union_blends_at_the_seam() {
+        let (a, b) = spheres();
+        let union = SmoothUnion { a, b, radius: 1.0 };
+        // further than the radius from the seam it is the hard union
+        assert_distance(&union, (-3.0, 0.0, 0.0), 0.5);
+        assert_eq!(albedo_at(&union, (-3.0, 0.0, 0.0)), (1, 0, 0).into());
+        // on the seam both operands are the same distance away, and the blend pulls the surface out a quarter radius
+        let seam_distance = 5f64.sqrt() - 1.5;
+        assert_distance(&union, (0.0, 2.0, 0.0), seam_distance - 0.25);
+        assert_eq!(albedo_at(&union, (0.0, 2.0, 0.0)), (0.5, 0.0, 0.5).into());
+    }
+
+    #[test]
+    fn smooth_intersection_blends_at_the_seam() {
+        let (a, b) = spheres();
+        let intersection = SmoothIntersection { a, b, radius: 1.0 };
+        assert_distance(&intersection, (2.0, 0.0, 0.0), 1.5);
+        assert_eq!(albedo_at(&intersection, (2.0, 0.0, 0.0)), (1, 0, 0).into());
+        let seam_distance = 5f64.sqrt() - 1.5;
+        assert_distance(&intersection, (0.0, 2.0, 0.0), seam_distance + 0.25);
+        assert_eq!(albedo_at(&intersection, (0.0, 2.0, 0.0)), (0.5, 0.0, 0.5).into());
+    }
+
+    #[test]
+    fn smooth_difference_blends_at_the_seam() {
+        let (a, b) = spheres();
+        let difference = SmoothDifference { a, b, radius: 1.0 };
+        assert_distance(&difference, (-2.0, 0.0, 0.0), -0.5);
+        assert_eq!(albedo_at(&difference, (-2.0, 0.0, 0.0)), (1, 0, 0).into());
+        // where the surfaces of a and b cross the blend rounds off the edge of the cut, leaving the rim outside it
+        let rim = (0.0, 1.25f64.sqrt(), 0.0);
+        assert_distance(&difference, rim, 0.25);
+        assert_eq!(albedo_at(&difference, rim), (0.5, 0.0, 0.5).into());
+    }
+}
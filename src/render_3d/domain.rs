@@ -0,0 +1,196 @@
+use super::material::Material;
+use super::scene_object::SceneObject;
+use crate::vec3::Vec3;
+use serde::Deserialize;
+
+// Domain operators fold space before handing the point to the wrapped object, so one object shows up in several
+// places without any extra distance evaluations.
+
+/// Tiles an object along each axis with a `spacing` of 0 meaning no repetition along that axis. A `count` limits
+/// the number of copies along an axis (centered on the origin), `None` repeats forever.
+///
+/// Distances stay correct as long as the object fits inside one cell.
+pub struct Repeat<O: SceneObject> {
+    pub object: O,
+    pub spacing: Vec3,
+    pub count: [Option<u32>; 3],
+}
+
+impl<O: SceneObject> Repeat<O> {
+    /// Map a point into the cell around the copy nearest to it.
+    fn to_cell(&self, point: Vec3) -> Vec3 {
+        Vec3 {
+            x: repeat_axis(point.x, self.spacing.x, self.count[0]),
+            y: repeat_axis(point.y, self.spacing.y, self.count[1]),
+            z: repeat_axis(point.z, self.spacing.z, self.count[2]),
+        }
+    }
+}
+
+fn repeat_axis(coord: f64, spacing: f64, count: Option<u32>) -> f64 {
+    if spacing == 0.0 {
+        return coord;
+    }
+    match count {
+        // round rather than % so that negative coordinates repeat the same way as positive ones
+        None => coord - spacing * (coord / spacing).round(),
+        Some(count) => {
+            // copies sit at (k - (count - 1) / 2) * spacing for k in 0..count
+            let half_span = (count as f64 - 1.0) / 2.0;
+            let k = (coord / spacing + half_span).round().clamp(0.0, count as f64 - 1.0);
+            coord - (k - half_span) * spacing
+        }
+    }
+}
+
+impl<O: SceneObject> SceneObject for Repeat<O> {
+    fn distance_to(&self, point: Vec3, t: f64) -> f64 {
+        self.object.distance_to(self.to_cell(point), t)
+    }
+
+    fn get_material(&self, t: f64) -> Material {
+        self.object.get_material(t)
+    }
+
+    fn material_at(&self, point: Vec3, normal: Vec3, t: f64) -> Material {
+        self.object.material_at(self.to_cell(point), normal, t)
+    }
+
+    fn normal(&self, p: Vec3, t: f64) -> Vec3 {
+        self.object.normal(self.to_cell(p), t)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+/// Reflects the positive side of an object across the plane through the origin perpendicular to `axis`, making it
+/// symmetric about that plane. Whatever was on the negative side is replaced by the reflection.
+pub struct Mirror<O: SceneObject> {
+    pub object: O,
+    pub axis: Axis,
+}
+
+impl<O: SceneObject> Mirror<O> {
+    fn fold(&self, point: Vec3) -> Vec3 {
+        match self.axis {
+            Axis::X => Vec3 { x: point.x.abs(), ..point },
+            Axis::Y => Vec3 { y: point.y.abs(), ..point },
+            Axis::Z => Vec3 { z: point.z.abs(), ..point },
+        }
+    }
+
+    /// Reflect a normal at `point` to or from the folded side. Reflecting is its own inverse, so this works both ways.
+    fn fold_normal(&self, point: Vec3, n: Vec3) -> Vec3 {
+        match self.axis {
+            Axis::X if point.x < 0.0 => Vec3 { x: -n.x, ..n },
+            Axis::Y if point.y < 0.0 => Vec3 { y: -n.y, ..n },
+            Axis::Z if point.z < 0.0 => Vec3 { z: -n.z, ..n },
+            _ => n,
+        }
+    }
+}
+
+impl<O: SceneObject> SceneObject for Mirror<O> {
+    fn distance_to(&self, point: Vec3, t: f64) -> f64 {
+        self.object.distance_to(self.fold(point), t)
+    }
+
+    fn get_material(&self, t: f64) -> Material {
+        self.object.get_material(t)
+    }
+
+    fn material_at(&self, point: Vec3, normal: Vec3, t: f64) -> Material {
+        self.object.material_at(self.fold(point), self.fold_normal(point, normal), t)
+    }
+
+    fn normal(&self, p: Vec3, t: f64) -> Vec3 {
+        // the normal of a reflected point is the reflected normal
+        self.fold_normal(p, self.object.normal(self.fold(p), t))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_3d::scene_object::Sphere;
+
+    const EPS: f64 = 1E-9;
+
+    fn sphere(x: f64) -> Sphere {
+        Sphere { center: (x, 0.0, 0.0).into(), radius: 0.5, material: Material::default() }
+    }
+
+    fn repeat(count: Option<u32>) -> Repeat<Sphere> {
+        Repeat { object: sphere(0.0), spacing: (2, 0, 0).into(), count: [count, None, None] }
+    }
+
+    fn assert_distance<O: SceneObject>(object: &O, x: f64, expected: f64) {
+        let dist = object.distance_to((x, 0.0, 0.0).into(), 0.0);
+        assert!((dist - expected).abs() < EPS, "distance at x = {} was {}, expected {}", x, dist, expected);
+    }
+
+    #[test]
+    fn repeat_is_the_same_either_side_of_the_origin() {
+        let object = repeat(None);
+        for &x in &[0.3, 1.2, 4.3, 11.9] {
+            let expected = object.distance_to((x, 0.0, 0.0).into(), 0.0);
+            assert_distance(&object, -x, expected);
+        }
+        assert_distance(&object, -4.3, -0.2);
+        assert_distance(&object, -5.0, 0.5);
+        // no spacing along y, so no repetition
+        assert!((object.distance_to((0, 6, 0).into(), 0.0) - 5.5).abs() < EPS);
+    }
+
+    #[test]
+    fn repeat_count_clamps_to_the_edge_copies() {
+        // copies at -2, 0 and 2
+        let odd = repeat(Some(3));
+        assert_distance(&odd, 2.3, -0.2);
+        assert_distance(&odd, 7.0, 4.5);
+        assert_distance(&odd, -7.0, 4.5);
+        // copies at -1 and 1, none at the origin
+        let even = repeat(Some(2));
+        assert_distance(&even, 0.0, 0.5);
+        assert_distance(&even, -1.0, -0.5);
+        assert_distance(&even, 4.0, 2.5);
+        assert_distance(&even, -4.0, 2.5);
+    }
+
+    /// Sphere whose albedo is the normal it is shaded with, to see which normal a wrapper passes on.
+    struct NormalColored(Sphere);
+
+    impl SceneObject for NormalColored {
+        fn distance_to(&self, point: Vec3, t: f64) -> f64 {
+            self.0.distance_to(point, t)
+        }
+
+        fn get_material(&self, t: f64) -> Material {
+            self.0.get_material(t)
+        }
+
+        fn material_at(&self, _: Vec3, normal: Vec3, _: f64) -> Material {
+            Material { albedo: normal, ..Material::default() }
+        }
+    }
+
+    #[test]
+    fn mirror_reflects_the_normal_back() {
+        let object = Mirror { object: NormalColored(sphere(1.0)), axis: Axis::X };
+        assert_distance(&object, -1.0, -0.5);
+        assert_distance(&object, -2.0, 0.5);
+        // on the reflected copy the outer side of the sphere faces -x
+        let normal = object.normal((-2, 0, 0).into(), 0.0);
+        assert!((normal - Vec3::from((-1, 0, 0))).magnitude() < 1E-6, "normal was {:?}", normal);
+        assert!((object.normal((2, 0, 0).into(), 0.0) - Vec3::from((1, 0, 0))).magnitude() < 1E-6);
+        // and the wrapped object is shaded with the normal it would have on the positive side
+        let shaded = object.material_at((-1.5, 0.0, 0.0).into(), (-1, 0, 0).into(), 0.0);
+        assert_eq!(shaded.albedo, (1, 0, 0).into());
+    }
+}
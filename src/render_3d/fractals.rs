@@ -1,26 +1,141 @@
+use super::material::Material;
 use super::scene_object::SceneObject;
+use crate::palette::Palette;
 use crate::vec3::Vec3;
 use cgmath::{Quaternion, InnerSpace, Zero, One};
+use serde::Deserialize;
 
 type Quaternion64 = Quaternion<f64>;
 
 const MAX_ITERS: i32 = 20;
 
+/// Colors a fractal by what its orbit does near a surface point, looking the value up in a palette to replace the
+/// albedo of its material.
+#[derive(Debug, Clone)]
+pub struct OrbitColoring {
+    pub source: OrbitSource,
+    pub palette: Palette,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "by", rename_all = "snake_case")]
+pub enum OrbitSource {
+    /// Closest the orbit comes to the trap, clamped to 1
+    Trap { trap: OrbitTrap },
+    /// Smoothed number of iterations before the orbit escapes, as a fraction of the iteration limit
+    Iterations,
+}
+
+/// Shape the orbit's closest approach is measured against. Orbits of quaternion fractals are traced through their
+/// first three components.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum OrbitTrap {
+    Point {
+        #[serde(default)]
+        position: Vec3,
+    },
+    /// Plane through the points where `z . normal == offset`
+    Plane {
+        normal: Vec3,
+        #[serde(default)]
+        offset: f64,
+    },
+    /// Infinite line through `point` along `direction`
+    Axis {
+        #[serde(default)]
+        point: Vec3,
+        direction: Vec3,
+    },
+    Sphere {
+        #[serde(default)]
+        center: Vec3,
+        radius: f64,
+    },
+}
+
+impl OrbitTrap {
+    fn distance(&self, z: Vec3) -> f64 {
+        match *self {
+            OrbitTrap::Point { position } => (z - position).magnitude(),
+            OrbitTrap::Plane { normal, offset } => (z.dot(normal.normalized()) - offset).abs(),
+            OrbitTrap::Axis { point, direction } => {
+                let rel = z - point;
+                let dir = direction.normalized();
+                (rel - rel.dot(dir) * dir).magnitude()
+            }
+            OrbitTrap::Sphere { center, radius } => ((z - center).magnitude() - radius).abs(),
+        }
+    }
+}
+
+/// Keeps track of the parts of an orbit needed for coloring while a fractal is iterated.
+struct Orbit<'a> {
+    source: Option<&'a OrbitSource>,
+    trap_distance: f64,
+}
+
+impl<'a> Orbit<'a> {
+    fn new(coloring: Option<&'a OrbitColoring>) -> Self {
+        Orbit {
+            source: coloring.map(|coloring| &coloring.source),
+            trap_distance: f64::INFINITY,
+        }
+    }
+
+    fn visit(&mut self, z: Vec3) {
+        if let Some(OrbitSource::Trap { trap }) = self.source {
+            self.trap_distance = self.trap_distance.min(trap.distance(z));
+        }
+    }
+
+    /// Coloring value from 0 to 1, given the number of iterations the orbit stayed bounded for, its magnitude when
+    /// iteration stopped and the power of the fractal's iteration.
+    fn value(&self, iters: i32, magnitude: f64, power: f64) -> f64 {
+        match self.source {
+            Some(OrbitSource::Trap { .. }) => self.trap_distance.min(1.0),
+            Some(OrbitSource::Iterations) if iters < MAX_ITERS => {
+                // the fractional part comes from how far past the bailout the orbit was when it escaped
+                let smooth = iters as f64 + 1.0 - magnitude.ln().ln() / power.ln();
+                (smooth / MAX_ITERS as f64).clamp(0.0, 1.0)
+            }
+            Some(OrbitSource::Iterations) => 1.0,
+            None => 0.0,
+        }
+    }
+}
+
+/// The fractal's material, with its albedo taken from the palette if there is orbit coloring.
+fn orbit_material(material: Material, coloring: &Option<OrbitColoring>, value: f64) -> Material {
+    match coloring {
+        Some(coloring) => Material { albedo: coloring.palette.color(value), ..material },
+        None => material,
+    }
+}
+
+fn quaternion_orbit_point(z: Quaternion64) -> Vec3 {
+    Vec3 { x: z.s, y: z.v.x, z: z.v.y }
+}
+
 pub struct Julia {
     pub c: Quaternion64,
-    pub color: Vec3,
+    pub material: Material,
+    pub coloring: Option<OrbitColoring>,
 }
 
-impl SceneObject for Julia {
-    fn distance_to(&self, point: Vec3, t: f64) -> f64 {
+impl Julia {
+    /// Returns the distance estimate and the orbit coloring value.
+    fn iterate(&self, point: Vec3, t: f64, coloring: Option<&OrbitColoring>) -> (f64, f64) {
         let mut z = Quaternion64::new(point.x, point.y, point.z, t);
         let mut dz = Quaternion64::new(1.0, 0.0, 0.0, 0.0);
         let mut count = 0;
+        let mut orbit = Orbit::new(coloring);
 
         while count < MAX_ITERS {
             let z_new = z * z + self.c;
             dz = 2.0 * z * dz;
             z = z_new;
+            orbit.visit(quaternion_orbit_point(z));
 
             if z.magnitude() > 4.0 {
                 break;
@@ -29,30 +144,48 @@ impl SceneObject for Julia {
         }
 
         let dist: f64 = z.magnitude() * z.magnitude().ln() / dz.magnitude();
-        dist * 0.2
+        (dist * 0.2, orbit.value(count, z.magnitude(), 2.0))
+    }
+}
+
+impl SceneObject for Julia {
+    fn distance_to(&self, point: Vec3, t: f64) -> f64 {
+        self.iterate(point, t, None).0
+    }
+
+    fn get_material(&self, _: f64) -> Material {
+        self.material
     }
 
-    fn get_color(&self, t: f64) -> Vec3 {
-        self.color
+    fn material_at(&self, point: Vec3, _: Vec3, t: f64) -> Material {
+        let (_, value) = self.iterate(point, t, self.coloring.as_ref());
+        orbit_material(self.material, &self.coloring, value)
     }
 }
 
+/// Albedo of the mandelbrot set when it isn't given one.
+pub const MANDELBROT_COLOR: Vec3 = Vec3 { x: 0.5, y: 0.5, z: 1.0 };
+
 pub struct Mandelbrot {
     pub w: f64,
-    pub size: f64,
+    pub material: Material,
+    pub coloring: Option<OrbitColoring>,
 }
 
-impl SceneObject for Mandelbrot {
-    fn distance_to(&self, point: Vec3, _: f64) -> f64 {
+impl Mandelbrot {
+    /// Returns the distance estimate and the orbit coloring value.
+    fn iterate(&self, point: Vec3, coloring: Option<&OrbitColoring>) -> (f64, f64) {
         let c = Quaternion64::new(point.x, point.y, point.z, self.w);
         let mut z = Quaternion64::zero();
         let mut dz = Quaternion64::new(1.0, 0.0, 0.0, 0.0);
         let mut count = 0;
+        let mut orbit = Orbit::new(coloring);
 
         while count < MAX_ITERS {
             let z_new = z * z + c;
             dz = 2.0 * z * dz + Quaternion64::one();
             z = z_new;
+            orbit.visit(quaternion_orbit_point(z));
 
             if z.magnitude() > 4.0 {
                 break;
@@ -61,27 +194,42 @@ impl SceneObject for Mandelbrot {
         }
 
         let dist: f64 = z.magnitude() * z.magnitude().ln() / dz.magnitude();
-        dist * 0.2
+        (dist * 0.2, orbit.value(count, z.magnitude(), 2.0))
+    }
+}
+
+impl SceneObject for Mandelbrot {
+    fn distance_to(&self, point: Vec3, _: f64) -> f64 {
+        self.iterate(point, None).0
     }
 
-    fn get_color(&self, _: f64) -> Vec3 {
-        (0.5, 0.5, 1.0).into()
+    fn get_material(&self, _: f64) -> Material {
+        self.material
+    }
+
+    fn material_at(&self, point: Vec3, _: Vec3, _: f64) -> Material {
+        let (_, value) = self.iterate(point, self.coloring.as_ref());
+        orbit_material(self.material, &self.coloring, value)
     }
 }
 
 pub struct Mandelbulb {
-    pub color: Vec3
+    pub power: f64,
+    pub material: Material,
+    pub coloring: Option<OrbitColoring>,
 }
 
-
-impl SceneObject for Mandelbulb {
-    fn distance_to(&self, point: Vec3, t: f64) -> f64 {
-        let power = 4.0;
+impl Mandelbulb {
+    /// Returns the distance estimate and the orbit coloring value.
+    fn iterate(&self, point: Vec3, coloring: Option<&OrbitColoring>) -> (f64, f64) {
+        let power = self.power;
         let mut z = point;
         let mut dr = 1.0;
         let mut r = 0.0;
+        let mut count = 0;
+        let mut orbit = Orbit::new(coloring);
 
-        for i in 0..MAX_ITERS {
+        while count < MAX_ITERS {
             r = z.magnitude();
             if r > 2.0 {
                 break;
@@ -92,18 +240,31 @@ impl SceneObject for Mandelbulb {
             dr = r.powf(power - 1.0) * power * dr + 1.0;
 
             let zr = r.powf(power);
-            theta = theta * power;
-            phi = phi * power;
+            theta *= power;
+            phi *= power;
 
             z = zr * Vec3::from((theta.sin() * phi.cos(), phi.sin() * theta.sin(), theta.cos()));
 
             z = z + point;
+            orbit.visit(z);
+            count += 1;
         }
 
-        0.5 * r * r.ln() / dr
+        (0.5 * r * r.ln() / dr, orbit.value(count, r, power))
     }
+}
 
-    fn get_color(&self, t: f64) -> Vec3 {
-        self.color
+impl SceneObject for Mandelbulb {
+    fn distance_to(&self, point: Vec3, _: f64) -> f64 {
+        self.iterate(point, None).0
+    }
+
+    fn get_material(&self, _: f64) -> Material {
+        self.material
     }
-}
\ No newline at end of file
+
+    fn material_at(&self, point: Vec3, _: Vec3, _: f64) -> Material {
+        let (_, value) = self.iterate(point, self.coloring.as_ref());
+        orbit_material(self.material, &self.coloring, value)
+    }
+}
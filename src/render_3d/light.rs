@@ -0,0 +1,288 @@
+use crate::vec3::Vec3;
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+/// In a scene file each `[[lights]]` table is one light, with a `type` key (point, directional, spot, hemisphere or
+/// ambient) and the same properties as on the command line.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "LightTable")]
+pub struct Light {
+    pub kind: LightKind,
+    pub color: Vec3,
+    pub intensity: f64,
+    /// Whether the light fades with the inverse square of the distance to it. Only used by point and spot lights
+    pub falloff: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum LightKind {
+    Point {
+        position: Vec3,
+    },
+    /// Light from infinitely far away, eg. the sun. `direction` is the direction the light travels in
+    Directional {
+        direction: Vec3,
+    },
+    /// Point light restricted to a cone of half angle `angle` degrees around `direction`. The angle is between 0 and
+    /// 180
+    Spot {
+        position: Vec3,
+        direction: Vec3,
+        angle: f64,
+    },
+    /// Ambient light that fades from the light's color on surfaces facing `up` to `ground_color` on surfaces facing
+    /// away from it
+    Hemisphere {
+        up: Vec3,
+        ground_color: Vec3,
+    },
+    /// Light that reaches every surface equally, apart from ambient occlusion
+    Ambient,
+}
+
+/// Light arriving from one direction, as seen from a point being shaded.
+pub struct Incidence {
+    /// Normalized direction from the point toward the light
+    pub dir: Vec3,
+    /// Distance to the light, infinite for directional lights
+    pub distance: f64,
+    /// Color and intensity of the light arriving at the point, before shadowing
+    pub radiance: Vec3,
+}
+
+impl Light {
+    /// Light arriving at `point` from a particular direction, or `None` for ambient lights.
+    pub fn incidence(&self, point: Vec3) -> Option<Incidence> {
+        let radiance = self.intensity * self.color;
+        match self.kind {
+            LightKind::Point { position } => {
+                let to_light = position - point;
+                Some(Incidence {
+                    dir: to_light.normalized(),
+                    distance: to_light.magnitude(),
+                    radiance: self.attenuation(to_light.magnitude()) * radiance,
+                })
+            }
+            LightKind::Directional { direction } => Some(Incidence {
+                dir: -direction.normalized(),
+                distance: f64::INFINITY,
+                radiance,
+            }),
+            LightKind::Spot { position, direction, angle } => {
+                let to_light = position - point;
+                let dir = to_light.normalized();
+                // fade out over the outer fifth of the cone rather than cutting off sharply
+                let cos_outer = angle.to_radians().cos();
+                let cos_inner = (0.8 * angle).to_radians().cos();
+                let cone = smoothstep(cos_outer, cos_inner, (-dir).dot(direction.normalized()));
+                Some(Incidence {
+                    dir,
+                    distance: to_light.magnitude(),
+                    radiance: cone * self.attenuation(to_light.magnitude()) * radiance,
+                })
+            }
+            LightKind::Hemisphere { .. } | LightKind::Ambient => None,
+        }
+    }
+
+    /// Ambient light reaching a surface with the given normal, before ambient occlusion. Black for lights that come
+    /// from a particular direction.
+    pub fn ambient(&self, normal: Vec3) -> Vec3 {
+        let radiance = self.intensity * self.color;
+        match self.kind {
+            LightKind::Hemisphere { up, ground_color } => {
+                let sky_amount = 0.5 + 0.5 * normal.dot(up.normalized());
+                (self.intensity * ground_color).lerp(radiance, sky_amount)
+            }
+            LightKind::Ambient => radiance,
+            _ => Vec3::default(),
+        }
+    }
+
+    fn attenuation(&self, distance: f64) -> f64 {
+        if self.falloff {
+            // offset by one so the light doesn't blow up right next to it
+            1.0 / (1.0 + distance * distance)
+        } else {
+            1.0
+        }
+    }
+}
+
+fn smoothstep(edge0: f64, edge1: f64, x: f64) -> f64 {
+    let x = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    x * x * (3.0 - 2.0 * x)
+}
+
+/// Every property a light can have, before checking which ones its type uses. Scene files and the command line both
+/// describe lights this way.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LightTable {
+    #[serde(rename = "type")]
+    light_type: String,
+    #[serde(alias = "pos")]
+    position: Option<Vec3>,
+    #[serde(alias = "dir")]
+    direction: Option<Vec3>,
+    angle: Option<f64>,
+    up: Option<Vec3>,
+    ground_color: Option<Vec3>,
+    color: Option<Vec3>,
+    intensity: Option<f64>,
+    #[serde(default)]
+    falloff: bool,
+}
+
+impl TryFrom<LightTable> for Light {
+    type Error = String;
+
+    fn try_from(table: LightTable) -> Result<Self, Self::Error> {
+        let light_type = table.light_type.as_str();
+        // properties the light's type doesn't use are mistakes, like misspelled ones
+        let mut unused = vec![
+            ("position", table.position.is_some()),
+            ("direction", table.direction.is_some()),
+            ("angle", table.angle.is_some()),
+            ("up", table.up.is_some()),
+            ("ground_color", table.ground_color.is_some()),
+        ];
+        let mut take = |key: &str| unused.retain(|&(unused_key, _)| unused_key != key);
+        let kind = match light_type {
+            "point" => {
+                take("position");
+                LightKind::Point { position: required(table.position, light_type, "position")? }
+            }
+            "directional" => {
+                take("direction");
+                LightKind::Directional { direction: required(table.direction, light_type, "direction")? }
+            }
+            "spot" => {
+                take("position");
+                take("direction");
+                take("angle");
+                let angle = required(table.angle, light_type, "angle")?;
+                // the cone's edge fades out between 80% and 100% of the angle, which can't be done for 0
+                if angle <= 0.0 || angle >= 180.0 {
+                    return Err(format!("spot light angle must be between 0 and 180 degrees, not {}", angle));
+                }
+                LightKind::Spot {
+                    position: required(table.position, light_type, "position")?,
+                    direction: required(table.direction, light_type, "direction")?,
+                    angle,
+                }
+            }
+            "hemisphere" => {
+                take("up");
+                take("ground_color");
+                LightKind::Hemisphere {
+                    up: table.up.unwrap_or_else(|| (0, 1, 0).into()),
+                    ground_color: table.ground_color.unwrap_or_default(),
+                }
+            }
+            "ambient" => LightKind::Ambient,
+            _ => return Err(format!(
+                "unknown light type '{}', expected point, directional, spot, hemisphere or ambient",
+                light_type
+            )),
+        };
+        if let Some((key, _)) = unused.iter().find(|&&(_, given)| given) {
+            return Err(format!("{} lights have no {} property", light_type, key));
+        }
+
+        Ok(Light {
+            kind,
+            color: table.color.unwrap_or_else(|| 1.into()),
+            intensity: table.intensity.unwrap_or(1.0),
+            falloff: table.falloff,
+        })
+    }
+}
+
+/// Parses the command line light syntax: the light type followed by colon separated `key=value` properties, eg.
+/// `point:position=2,4,4:color=1,0.9,0.8`, `spot:position=0,4,0:direction=0,-1,0:angle=30:falloff` or
+/// `ambient:color=0.1,0.1,0.1`. The keys are the same as in a scene file.
+impl FromStr for Light {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let mut table = LightTable {
+            light_type: parts.next().unwrap_or_default().to_string(),
+            ..LightTable::default()
+        };
+
+        for property in parts {
+            let (key, value) = match property.find('=') {
+                Some(i) => (&property[..i], &property[i + 1..]),
+                None => (property, ""),
+            };
+            match key {
+                "position" | "pos" => table.position = Some(parse_vec3(key, value)?),
+                "direction" | "dir" => table.direction = Some(parse_vec3(key, value)?),
+                "angle" => table.angle = Some(parse_f64(key, value)?),
+                "up" => table.up = Some(parse_vec3(key, value)?),
+                "ground_color" => table.ground_color = Some(parse_vec3(key, value)?),
+                "color" => table.color = Some(parse_vec3(key, value)?),
+                "intensity" => table.intensity = Some(parse_f64(key, value)?),
+                "falloff" => table.falloff = true,
+                _ => return Err(format!("unknown light property '{}'", key)),
+            }
+        }
+        Light::try_from(table)
+    }
+}
+
+fn required<T>(value: Option<T>, light_type: &str, key: &str) -> Result<T, String> {
+    value.ok_or(format!("{} lights need a {} property", light_type, key))
+}
+
+fn parse_f64(key: &str, value: &str) -> Result<f64, String> {
+    value.parse().map_err(|_| format!("{} must be a number", key))
+}
+
+fn parse_vec3(key: &str, value: &str) -> Result<Vec3, String> {
+    let values = value
+        .split(',')
+        .map(|v| v.parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("{} must be three comma separated numbers", key))?;
+    match values[..] {
+        [x, y, z] => Ok(Vec3 { x, y, z }),
+        _ => Err(format!("{} must be three comma separated numbers", key)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_line_and_scene_lights_agree() {
+        let parsed: Light = "hemisphere:up=0,0,1:ground_color=0.1,0.2,0.3:intensity=2".parse().unwrap();
+        let table: Light = toml::from_str("type = \"hemisphere\"\nup = [0, 0, 1]\nground_color = [0.1, 0.2, 0.3]\n\
+            intensity = 2.0").unwrap();
+        for light in &[parsed, table] {
+            match light.kind {
+                LightKind::Hemisphere { up, ground_color } => {
+                    assert_eq!(up, Vec3::from((0, 0, 1)));
+                    assert_eq!(ground_color, Vec3::from((0.1, 0.2, 0.3)));
+                }
+                _ => panic!("expected a hemisphere light"),
+            }
+            assert_eq!(light.intensity, 2.0);
+        }
+    }
+
+    #[test]
+    fn invalid_lights_are_rejected() {
+        assert!("spot:position=0,4,0:direction=0,-1,0:angle=0".parse::<Light>().is_err());
+        assert!("spot:position=0,4,0:direction=0,-1,0".parse::<Light>().is_err());
+        assert!("point:position=0,4,0:angle=30".parse::<Light>().is_err());
+        assert!("hemisphere:ground=0,0,0".parse::<Light>().is_err());
+        assert!(toml::from_str::<Light>("type = \"point\"\npositon = [0, 4, 0]").is_err());
+        assert!(toml::from_str::<Light>("type = \"spot\"\nposition = [0, 4, 0]\ndirection = [0, -1, 0]\nangle = 180.0")
+            .is_err());
+    }
+}
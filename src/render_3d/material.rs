@@ -0,0 +1,164 @@
+use crate::range;
+use crate::vec3::Vec3;
+use serde::Deserialize;
+
+/// How the surface of an object interacts with light. Any property left out of a scene file keeps its default.
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Material {
+    /// Base color of the surface, ie. the fraction of each color channel of incoming light it scatters diffusely
+    pub albedo: Vec3,
+    /// Color of the highlights the lights leave on the surface
+    pub specular_color: Vec3,
+    /// Shininess exponent of the phong and blinn shading models. Larger is a smaller, sharper highlight
+    pub shininess: f64,
+    /// From 0 (a dielectric, eg. plastic or stone) to 1 (a metal). Only used by pbr shading, where metals have no
+    /// diffuse color and tint their highlights with the albedo instead
+    #[serde(deserialize_with = "range::unit_interval")]
+    pub metallic: f64,
+    /// Fraction of light reflected like a mirror when looking straight at the surface, from 0 (not reflective) to 1
+    /// (a perfect mirror). Reflections get stronger towards grazing angles
+    #[serde(deserialize_with = "range::unit_interval")]
+    pub reflectivity: f64,
+    /// Light given off by the surface itself, regardless of the lights in the scene
+    pub emission: Vec3,
+    /// From 0 (smooth) to 1 (rough). Rough surfaces scatter their reflections, which dims them, and with pbr shading
+    /// they spread their highlights out
+    #[serde(deserialize_with = "range::unit_interval")]
+    pub roughness: f64,
+    /// How light passes through the object, `None` for opaque objects
+    pub transparency: Option<Transparency>,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            albedo: Vec3 { x: 0.8, y: 0.0, z: 0.0 },
+            specular_color: Vec3 { x: 1.0, y: 1.0, z: 1.0 },
+            shininess: 50.0,
+            metallic: 0.0,
+            reflectivity: 0.0,
+            emission: Vec3::default(),
+            roughness: 0.0,
+            transparency: None,
+        }
+    }
+}
+
+impl Material {
+    /// Blend towards another material, with `t` from 0 (all `self`) to 1 (all `other`). Transparency can't be
+    /// blended, so it comes from whichever material contributes the most.
+    pub fn lerp(&self, other: &Material, t: f64) -> Material {
+        let mix = |a: f64, b: f64| a + t * (b - a);
+        Material {
+            albedo: self.albedo.lerp(other.albedo, t),
+            specular_color: self.specular_color.lerp(other.specular_color, t),
+            shininess: mix(self.shininess, other.shininess),
+            metallic: mix(self.metallic, other.metallic),
+            reflectivity: mix(self.reflectivity, other.reflectivity),
+            emission: self.emission.lerp(other.emission, t),
+            roughness: mix(self.roughness, other.roughness),
+            transparency: if t < 0.5 { self.transparency } else { other.transparency },
+        }
+    }
+}
+
+/// Properties replacing those of every material of an object, for settings given for the whole scene. `None` keeps
+/// each material's own.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct MaterialOverrides {
+    pub albedo: Option<Vec3>,
+    pub specular_color: Option<Vec3>,
+    pub shininess: Option<f64>,
+    pub metallic: Option<f64>,
+    pub reflectivity: Option<f64>,
+    pub roughness: Option<f64>,
+    /// Makes every material transparent, with this index of refraction
+    pub ior: Option<f64>,
+    /// Transmission of the materials that are transparent
+    pub transmission: Option<Vec3>,
+}
+
+impl MaterialOverrides {
+    pub fn is_empty(&self) -> bool {
+        *self == MaterialOverrides::default()
+    }
+
+    pub fn apply(&self, material: Material) -> Material {
+        let transparency = match (self.ior, material.transparency) {
+            (Some(ior), Some(transparency)) => Some(Transparency { ior, ..transparency }),
+            (Some(ior), None) => Some(Transparency { ior, transmission: default_transmission() }),
+            (None, transparency) => transparency,
+        };
+        Material {
+            albedo: self.albedo.unwrap_or(material.albedo),
+            specular_color: self.specular_color.unwrap_or(material.specular_color),
+            shininess: self.shininess.unwrap_or(material.shininess),
+            metallic: self.metallic.unwrap_or(material.metallic),
+            reflectivity: self.reflectivity.unwrap_or(material.reflectivity),
+            roughness: self.roughness.unwrap_or(material.roughness),
+            transparency: transparency.map(|transparency| Transparency {
+                transmission: self.transmission.unwrap_or(transparency.transmission),
+                ..transparency
+            }),
+            ..material
+        }
+    }
+}
+
+/// How light passes through a transparent object.
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Transparency {
+    /// Index of refraction, eg. 1.33 for water or 1.5 for glass
+    #[serde(deserialize_with = "range::ior")]
+    pub ior: f64,
+    /// Fraction of each color channel that survives travelling one unit through the object. Light is absorbed
+    /// exponentially with distance (the Beer-Lambert law), so thick parts of the object are more strongly tinted
+    #[serde(default = "default_transmission")]
+    pub transmission: Vec3,
+}
+
+impl Transparency {
+    /// Fraction of each color channel left after light travels `distance` through the object.
+    pub fn absorption(&self, distance: f64) -> Vec3 {
+        Vec3 {
+            x: self.transmission.x.powf(distance),
+            y: self.transmission.y.powf(distance),
+            z: self.transmission.z.powf(distance),
+        }
+    }
+}
+
+fn default_transmission() -> Vec3 {
+    1.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_replace_only_what_they_set() {
+        let glass = Material {
+            transparency: Some(Transparency { ior: 1.5, transmission: (0.5, 0.5, 0.5).into() }),
+            ..Material::default()
+        };
+        let overrides = MaterialOverrides { ior: Some(1.33), reflectivity: Some(0.5), ..MaterialOverrides::default() };
+        let material = overrides.apply(glass);
+        assert_eq!(material.transparency, Some(Transparency { ior: 1.33, transmission: (0.5, 0.5, 0.5).into() }));
+        assert_eq!(material.reflectivity, 0.5);
+        assert_eq!(material.albedo, glass.albedo);
+        // opaque materials become clear glass
+        assert_eq!(overrides.apply(Material::default()).transparency.unwrap().transmission, 1.into());
+        assert!(MaterialOverrides::default().is_empty());
+    }
+
+    #[test]
+    fn ior_is_at_least_one() {
+        let water: Transparency = toml::from_str("ior = 1.33").unwrap();
+        assert_eq!(water.ior, 1.33);
+        assert!(toml::from_str::<Transparency>("ior = 0.8").is_err());
+        assert!(toml::from_str::<Transparency>("ior = -1.5").is_err());
+    }
+}
@@ -1,14 +1,29 @@
+use std::path::{Path, PathBuf};
 use std::time::Duration;
+use image::ImageResult;
 use minifb::{Key, Window, WindowOptions};
 
+use ray::{cast_ray, RayOutcome};
 use raymarcher::{RayMarcher, RayMarcherConfig, ImageRenderConfiguration};
 use scene_object::SceneObject;
 
 pub mod raymarcher;
+pub mod camera;
 pub mod scene_object;
 mod ray;
 pub mod fractals;
-mod sectioned;
+pub mod sectioned;
+pub mod csg;
+pub mod transform;
+pub mod primitives;
+pub mod domain;
+pub mod light;
+pub mod coloring;
+pub mod material;
+pub mod shading;
+mod sampling;
+mod path;
+pub mod stereo;
 
 pub fn main<O: SceneObject>(
     width: usize,
@@ -44,4 +59,53 @@ pub fn main<O: SceneObject>(
             println!("finished");
         }
     }
-}
\ No newline at end of file
+}
+
+/// Render a single frame of the scene straight to a PNG file (or files), without opening a window. Returns the paths
+/// written.
+pub fn render<O: SceneObject>(
+    width: usize,
+    height: usize,
+    config: RayMarcherConfig,
+    object: O,
+    output: &Path) -> ImageResult<Vec<PathBuf>> {
+    let raymarcher = RayMarcher {
+        object,
+        config,
+    };
+
+    raymarcher.render_to_image(output, (width, height), 0.0)
+}
+
+/// Render a numbered sequence of PNG frames, sweeping the time parameter t. `scene` builds the config and object for
+/// each frame from its index, so anything keyframed can change from one frame to the next.
+pub fn animate<O: SceneObject, S: Fn(u32) -> (RayMarcherConfig, O), F: Fn(u32) -> String>(
+    scene: S,
+    image_config: ImageRenderConfiguration<F>) -> ImageResult<()> {
+    for i in 0..image_config.frames {
+        let (config, object) = scene(i);
+        let raymarcher = RayMarcher {
+            object,
+            config,
+        };
+
+        raymarcher.render_frame(&image_config, i)?;
+    }
+    Ok(())
+}
+
+/// Distance along the view direction from the camera to the surface seen in the middle of the pixel `(x, y)`, or
+/// `None` if there's nothing there at t = 0. Used to focus the camera on whatever is in that pixel.
+pub fn focus_distance_at<O: SceneObject>(
+    config: &RayMarcherConfig,
+    object: &O,
+    (x, y): (usize, usize),
+    size: (usize, usize)) -> Option<f64> {
+    let (origin, dir) = config.camera.ray(x as f64 + 0.5, y as f64 + 0.5, size)?;
+    let res = cast_ray(object, origin, dir, f64::INFINITY, 0.0, config);
+    if res.outcome == RayOutcome::Hit {
+        Some(res.len * dir.dot(config.camera.basis().forward))
+    } else {
+        None
+    }
+}
@@ -0,0 +1,150 @@
+use super::material::{Material, Transparency};
+use super::ray::{cast_ray, RayOutcome};
+use super::raymarcher::{ior_reflectivity, schlick_fresnel, Inside, RayMarcher, SURFACE_OFFSET};
+use super::sampling::Rng;
+use super::scene_object::SceneObject;
+use super::shading::Reflectance;
+use crate::vec3::Vec3;
+
+// Monte Carlo path tracing. Each sample follows one random path of bounces through the scene, picking up the light
+// that reaches every surface along it directly from the lights (next event estimation). Averaging many samples
+// converges on the full global illumination, including light bounced into cavities that direct lighting leaves dark.
+
+/// Paths are cut off after this many bounces even if Russian roulette hasn't ended them, so a ray trapped inside a
+/// transparent object can't bounce forever
+const MAX_PATH_LENGTH: u32 = 64;
+/// Number of bounces before Russian roulette starts ending paths
+const ROULETTE_START: u32 = 3;
+
+impl<O: SceneObject> RayMarcher<O> {
+    /// Estimate of the light arriving back along a ray, from one random path starting at `point` along `dir`.
+    pub(super) fn path_trace(&self, point: Vec3, dir: Vec3, t: f64, rng: &mut Rng) -> Vec3 {
+        let mut radiance = Vec3::default();
+        // fraction of the light found further along the path that makes it back to the camera
+        let mut throughput = Vec3::from(1);
+        let mut point = point;
+        let mut dir = dir.normalized();
+        // transparency of the object the path is travelling through, if it is inside one
+        let mut medium: Option<Transparency> = None;
+        // ambient lights only reach the camera through bounces that scatter off a surface, not mirror reflections
+        let mut after_scatter = false;
+
+        for bounce in 0..MAX_PATH_LENGTH {
+            if bounce >= ROULETTE_START {
+                // end dim paths at random, and boost the ones that survive so the estimate stays unbiased
+                let survival = throughput.max_component().min(0.95);
+                if rng.next_f64() >= survival {
+                    break;
+                }
+                throughput = (1.0 / survival) * throughput;
+            }
+
+            if let Some(transparency) = medium {
+                let res = cast_ray(&Inside(&self.object), point, dir, f64::INFINITY, t, &self.config);
+                throughput = throughput * transparency.absorption(res.len);
+                if res.outcome != RayOutcome::Hit {
+                    radiance = radiance + throughput * self.escaped(res.outcome, dir, false);
+                    break;
+                }
+                // facing back into the object, towards the ray
+                let norm = -self.object.normal(res.hit_point, t);
+                let refracted_dir = dir.refract(norm, transparency.ior);
+                if refracted_dir == Vec3::default() {
+                    point = res.hit_point + SURFACE_OFFSET * norm;
+                    dir = dir.reflect(norm).normalized();
+                } else {
+                    point = res.hit_point - SURFACE_OFFSET * norm;
+                    dir = refracted_dir.normalized();
+                    medium = None;
+                }
+                continue;
+            }
+
+            let res = cast_ray(&self.object, point, dir, f64::INFINITY, t, &self.config);
+            if res.outcome != RayOutcome::Hit {
+                radiance = radiance + throughput * self.escaped(res.outcome, dir, after_scatter);
+                break;
+            }
+
+            let norm = self.object.normal(res.hit_point - 1E-7 * dir, t);
+            let view_vec = -dir;
+            let material = self.object.material_at(res.hit_point, norm, t);
+            let outside_start = res.hit_point + SURFACE_OFFSET * norm;
+            let direct = self.direct_light(outside_start, &material, norm, view_vec, t);
+
+            if let Some(transparency) = material.transparency {
+                radiance = radiance + throughput * (direct.specular + material.emission);
+                let reflectivity = material.reflectivity.max(ior_reflectivity(transparency.ior));
+                // pick between the reflection and the refraction in proportion to how much light each carries
+                if rng.next_f64() < schlick_fresnel(reflectivity, view_vec.dot(norm)) {
+                    point = outside_start;
+                    dir = dir.reflect(norm).normalized();
+                } else {
+                    point = res.hit_point - SURFACE_OFFSET * norm;
+                    dir = dir.refract(norm, 1.0 / transparency.ior).normalized();
+                    medium = Some(transparency);
+                }
+                after_scatter = false;
+                continue;
+            }
+
+            let reflectivity = material.reflectivity * (1.0 - material.roughness.clamp(0.0, 1.0));
+            let fresnel = if reflectivity > 0.0 { schlick_fresnel(reflectivity, view_vec.dot(norm)) } else { 0.0 };
+            // the light the surface sends out itself is shared with the mirror reflection the same way as in `trace`
+            let local = direct.diffuse * material.albedo + direct.specular + material.emission;
+            radiance = radiance + (1.0 - fresnel) * (throughput * local);
+
+            point = outside_start;
+            if rng.next_f64() < fresnel {
+                dir = dir.reflect(norm).normalized();
+                after_scatter = false;
+            } else {
+                match self.config.shading.scatter(&material, norm, view_vec, rng) {
+                    Some((scattered, weight)) => {
+                        dir = scattered;
+                        throughput = throughput * weight;
+                        after_scatter = true;
+                    }
+                    None => break,
+                }
+            }
+        }
+        radiance
+    }
+
+    /// Light reaching a surface point straight from the lights, scattered towards the viewer. Shadows are always hard
+    /// since the lights are points.
+    fn direct_light(&self, point: Vec3, material: &Material, normal: Vec3, view: Vec3, t: f64) -> Reflectance {
+        let mut direct = Reflectance { diffuse: Vec3::default(), specular: Vec3::default() };
+        for light in &self.config.lights {
+            let incidence = match light.incidence(point) {
+                Some(incidence) => incidence,
+                None => continue,
+            };
+            if normal.dot(incidence.dir) <= 0.0 || incidence.radiance.max_component() <= 0.0 {
+                continue;
+            }
+            let shadow_ray = cast_ray(&self.object, point, incidence.dir, incidence.distance, t, &self.config);
+            if shadow_ray.outcome == RayOutcome::Hit {
+                continue;
+            }
+            let reflectance = self.config.shading.reflectance(material, normal, view, incidence.dir);
+            direct.diffuse = direct.diffuse + reflectance.diffuse * incidence.radiance;
+            direct.specular = direct.specular + reflectance.specular * incidence.radiance;
+        }
+        direct
+    }
+
+    /// Light arriving along a ray that left the scene. Ambient and hemisphere lights surround the scene and light
+    /// surfaces through scattered bounces, while the background is only seen directly or in reflections, like in
+    /// `trace`.
+    fn escaped(&self, outcome: RayOutcome, dir: Vec3, after_scatter: bool) -> Vec3 {
+        if after_scatter {
+            self.config.lights.iter().fold(Vec3::default(), |sum, light| sum + light.ambient(dir))
+        } else if outcome == RayOutcome::MaxSteps {
+            self.config.max_steps_color.unwrap_or(self.config.background_color)
+        } else {
+            self.config.background_color
+        }
+    }
+}
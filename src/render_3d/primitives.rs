@@ -0,0 +1,283 @@
+use super::material::Material;
+use super::scene_object::SceneObject;
+use crate::vec3::Vec3;
+
+// Analytic signed distance functions. Unless noted otherwise each primitive is centered on the origin with its
+// axis along y. Use `transform::Transformed` to place them elsewhere.
+
+/// Infinite plane through the points where `point . normal == height`. The side the normal points to is outside.
+pub struct Plane {
+    pub normal: Vec3,
+    pub height: f64,
+    pub material: Material,
+}
+
+impl SceneObject for Plane {
+    fn distance_to(&self, point: Vec3, _: f64) -> f64 {
+        point.dot(self.normal.normalized()) - self.height
+    }
+
+    fn get_material(&self, _: f64) -> Material {
+        self.material
+    }
+}
+
+/// Axis aligned box, `half_size` is the distance from the center to each face.
+pub struct Cuboid {
+    pub half_size: Vec3,
+    pub material: Material,
+}
+
+impl SceneObject for Cuboid {
+    fn distance_to(&self, point: Vec3, _: f64) -> f64 {
+        let q = point.abs() - self.half_size;
+        q.max(Vec3::default()).magnitude() + q.max_component().min(0.0)
+    }
+
+    fn get_material(&self, _: f64) -> Material {
+        self.material
+    }
+}
+
+/// Axis aligned box with its edges rounded off by `radius`. `half_size` includes the rounding.
+pub struct RoundedCuboid {
+    pub half_size: Vec3,
+    pub radius: f64,
+    pub material: Material,
+}
+
+impl SceneObject for RoundedCuboid {
+    fn distance_to(&self, point: Vec3, _: f64) -> f64 {
+        let q = point.abs() - self.half_size + self.radius;
+        q.max(Vec3::default()).magnitude() + q.max_component().min(0.0) - self.radius
+    }
+
+    fn get_material(&self, _: f64) -> Material {
+        self.material
+    }
+}
+
+/// Torus lying in the xz plane. `major_radius` is from the center to the middle of the tube, `minor_radius` is the
+/// radius of the tube.
+pub struct Torus {
+    pub major_radius: f64,
+    pub minor_radius: f64,
+    pub material: Material,
+}
+
+impl SceneObject for Torus {
+    fn distance_to(&self, point: Vec3, _: f64) -> f64 {
+        let ring_dist = xz_length(point) - self.major_radius;
+        (ring_dist * ring_dist + point.y * point.y).sqrt() - self.minor_radius
+    }
+
+    fn get_material(&self, _: f64) -> Material {
+        self.material
+    }
+}
+
+/// Capped cylinder, extending `half_height` above and below the xz plane.
+pub struct Cylinder {
+    pub radius: f64,
+    pub half_height: f64,
+    pub material: Material,
+}
+
+impl SceneObject for Cylinder {
+    fn distance_to(&self, point: Vec3, _: f64) -> f64 {
+        let radial = xz_length(point) - self.radius;
+        let axial = point.y.abs() - self.half_height;
+        let outside = (radial.max(0.0).powi(2) + axial.max(0.0).powi(2)).sqrt();
+        outside + radial.max(axial).min(0.0)
+    }
+
+    fn get_material(&self, _: f64) -> Material {
+        self.material
+    }
+}
+
+/// Line segment from `a` to `b` swept by a sphere of `radius`.
+pub struct Capsule {
+    pub a: Vec3,
+    pub b: Vec3,
+    pub radius: f64,
+    pub material: Material,
+}
+
+impl SceneObject for Capsule {
+    fn distance_to(&self, point: Vec3, _: f64) -> f64 {
+        let pa = point - self.a;
+        let ba = self.b - self.a;
+        let h = (pa.dot(ba) / ba.dot(ba)).clamp(0.0, 1.0);
+        (pa - h * ba).magnitude() - self.radius
+    }
+
+    fn get_material(&self, _: f64) -> Material {
+        self.material
+    }
+}
+
+/// Solid cone with its base disk of `radius` on the xz plane and its tip `height` above it.
+pub struct Cone {
+    pub radius: f64,
+    pub height: f64,
+    pub material: Material,
+}
+
+impl SceneObject for Cone {
+    fn distance_to(&self, point: Vec3, _: f64) -> f64 {
+        // work in the 2d (radial, height) cross section, relative to the tip. q runs from the tip to the base rim
+        let (qx, qy) = (self.radius, -self.height);
+        let (wx, wy) = (xz_length(point), point.y - self.height);
+
+        // closest point on the slanted side
+        let side = ((wx * qx + wy * qy) / (qx * qx + qy * qy)).clamp(0.0, 1.0);
+        let (ax, ay) = (wx - qx * side, wy - qy * side);
+        // closest point on the base
+        let (bx, by) = (wx - qx * (wx / qx).clamp(0.0, 1.0), wy - qy);
+
+        let dist_squared = (ax * ax + ay * ay).min(bx * bx + by * by);
+        let inside = (wy * qx - wx * qy).max(qy - wy) < 0.0;
+        if inside {
+            -dist_squared.sqrt()
+        } else {
+            dist_squared.sqrt()
+        }
+    }
+
+    fn get_material(&self, _: f64) -> Material {
+        self.material
+    }
+}
+
+/// Ellipsoid with the given radius along each axis. The distance is a bound rather than exact, except along the axes.
+pub struct Ellipsoid {
+    pub radii: Vec3,
+    pub material: Material,
+}
+
+impl SceneObject for Ellipsoid {
+    fn distance_to(&self, point: Vec3, _: f64) -> f64 {
+        let r = self.radii;
+        let k0 = Vec3::from((point.x / r.x, point.y / r.y, point.z / r.z)).magnitude();
+        let k1 = Vec3::from((point.x / (r.x * r.x), point.y / (r.y * r.y), point.z / (r.z * r.z))).magnitude();
+        if k1 == 0.0 {
+            // at the center, where the gradient estimate divides by zero
+            -r.x.min(r.y).min(r.z)
+        } else {
+            k0 * (k0 - 1.0) / k1
+        }
+    }
+
+    fn get_material(&self, _: f64) -> Material {
+        self.material
+    }
+}
+
+fn xz_length(point: Vec3) -> f64 {
+    (point.x * point.x + point.z * point.z).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPS: f64 = 1E-12;
+
+    fn assert_distance<O: SceneObject>(object: &O, point: (f64, f64, f64), expected: f64) {
+        let dist = object.distance_to(point.into(), 0.0);
+        assert!((dist - expected).abs() < EPS, "distance at {:?} was {}, expected {}", point, dist, expected);
+    }
+
+    fn material() -> Material {
+        Material::default()
+    }
+
+    #[test]
+    fn plane_distance() {
+        let plane = Plane { normal: (0.0, 2.0, 0.0).into(), height: -1.0, material: material() };
+        assert_distance(&plane, (5.0, 1.0, -3.0), 2.0);
+        assert_distance(&plane, (0.0, -1.0, 0.0), 0.0);
+        assert_distance(&plane, (0.0, -3.5, 0.0), -2.5);
+    }
+
+    #[test]
+    fn cuboid_distance() {
+        let cuboid = Cuboid { half_size: (1.0, 2.0, 3.0).into(), material: material() };
+        // facing a face
+        assert_distance(&cuboid, (3.0, 0.0, 0.0), 2.0);
+        assert_distance(&cuboid, (0.0, 0.0, -4.0), 1.0);
+        // off a corner
+        assert_distance(&cuboid, (2.0, 3.0, 3.0), 2f64.sqrt());
+        // inside, nearest the x faces
+        assert_distance(&cuboid, (0.0, 0.0, 0.0), -1.0);
+        assert_distance(&cuboid, (0.5, 0.0, 0.0), -0.5);
+    }
+
+    #[test]
+    fn rounded_cuboid_distance() {
+        let cuboid = RoundedCuboid { half_size: (1.0, 1.0, 1.0).into(), radius: 0.25, material: material() };
+        // faces are unaffected by the rounding
+        assert_distance(&cuboid, (2.0, 0.0, 0.0), 1.0);
+        assert_distance(&cuboid, (0.0, 0.0, 0.0), -1.0);
+        // along the diagonal the corner is a sphere of the rounding radius centered at 0.75, 0.75, 0.75
+        assert_distance(&cuboid, (2.0, 2.0, 2.0), 1.25 * 3f64.sqrt() - 0.25);
+    }
+
+    #[test]
+    fn torus_distance() {
+        let torus = Torus { major_radius: 2.0, minor_radius: 0.5, material: material() };
+        assert_distance(&torus, (0.0, 0.0, 0.0), 1.5);
+        assert_distance(&torus, (2.0, 0.0, 0.0), -0.5);
+        assert_distance(&torus, (0.0, 1.0, -2.0), 0.5);
+        assert_distance(&torus, (5.0, 0.0, 0.0), 2.5);
+    }
+
+    #[test]
+    fn cylinder_distance() {
+        let cylinder = Cylinder { radius: 1.0, half_height: 2.0, material: material() };
+        assert_distance(&cylinder, (3.0, 0.0, 0.0), 2.0);
+        assert_distance(&cylinder, (0.0, 5.0, 0.0), 3.0);
+        // off the rim of the top cap
+        assert_distance(&cylinder, (0.0, 3.0, -2.0), 2f64.sqrt());
+        assert_distance(&cylinder, (0.0, 0.0, 0.0), -1.0);
+        assert_distance(&cylinder, (0.0, 1.5, 0.0), -0.5);
+    }
+
+    #[test]
+    fn capsule_distance() {
+        let capsule = Capsule { a: (0, -1, 0).into(), b: (0, 1, 0).into(), radius: 0.5, material: material() };
+        assert_distance(&capsule, (2.0, 0.0, 0.0), 1.5);
+        // past the ends the capsule is a sphere around the end point
+        assert_distance(&capsule, (0.0, 4.0, 0.0), 2.5);
+        assert_distance(&capsule, (0.0, -1.0, 3.0), 2.5);
+        assert_distance(&capsule, (0.0, 0.5, 0.0), -0.5);
+    }
+
+    #[test]
+    fn cone_distance() {
+        // 3-4-5 triangle cross section so the slant distances are exact
+        let cone = Cone { radius: 3.0, height: 4.0, material: material() };
+        // above the tip
+        assert_distance(&cone, (0.0, 6.0, 0.0), 2.0);
+        // below the middle of the base
+        assert_distance(&cone, (0.0, -1.0, 0.0), 1.0);
+        // off the base rim, away from both the base and the slant
+        assert_distance(&cone, (3.0, -1.0, 0.0), 1.0);
+        // perpendicular to the slanted side, from its midpoint at (1.5, 2)
+        assert_distance(&cone, (1.5 + 0.8, 2.0 + 0.6, 0.0), 1.0);
+        // on the base
+        assert_distance(&cone, (0.0, 0.0, 1.9), 0.0);
+        // inside, closer to the base than the side
+        assert_distance(&cone, (0.0, 0.5, 0.0), -0.5);
+    }
+
+    #[test]
+    fn ellipsoid_distance() {
+        let ellipsoid = Ellipsoid { radii: (1.0, 2.0, 3.0).into(), material: material() };
+        assert_distance(&ellipsoid, (3.0, 0.0, 0.0), 2.0);
+        assert_distance(&ellipsoid, (0.0, -5.0, 0.0), 3.0);
+        assert_distance(&ellipsoid, (0.0, 0.0, 3.0), 0.0);
+        assert_distance(&ellipsoid, (0.0, 0.0, 0.0), -1.0);
+    }
+}
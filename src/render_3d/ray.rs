@@ -1,41 +1,82 @@
 use crate::vec3::Vec3;
+use super::raymarcher::RayMarcherConfig;
 use super::scene_object::SceneObject;
 
-const MAX_STEPS: u32 = 200;
 const HIT_THRESHOLD: f64 = 1E-4;
 
+/// Why a march along a ray stopped.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RayOutcome {
+    /// The ray came within the hit threshold of the object.
+    Hit,
+    /// The ray passed one of the backplanes, or its maximum length, without hitting anything.
+    Escaped,
+    /// The ray used up the configured maximum number of steps, eg. while grazing the surface or stepping through
+    /// a region where the distance estimate is NaN.
+    MaxSteps,
+}
+
 #[derive(Debug)]
 pub struct RayResult {
+    pub outcome: RayOutcome,
+    /// Distance travelled along the ray
     pub len: f64,
+    /// Point where the march stopped. Only on the surface if the outcome is `Hit`.
     pub hit_point: Vec3,
+    /// Smallest ratio of distance to the object over distance along the ray seen during the march, ie. the tangent
+    /// of the narrowest cone around the ray that the object touches. Used for soft shadow penumbras.
+    pub closest_approach: f64,
 }
 
-pub fn cast_ray<O: SceneObject>(object: &O, point: Vec3, dir: Vec3, backplanes: Vec3, t: f64) -> Option<RayResult> {
+/// March a ray from `point` along `dir` until it hits the object, passes a backplane or travels `max_len`.
+pub fn cast_ray<O: SceneObject>(
+    object: &O,
+    point: Vec3,
+    dir: Vec3,
+    max_len: f64,
+    t: f64,
+    config: &RayMarcherConfig) -> RayResult {
     let dir = dir.normalized();
-    let mut current_point = point.clone();
-    let mut iterations = 0u32;
+    let backplanes = config.backplane_positions;
+    let mut current_point = point;
     let mut ray_len = 0.0;
+    let mut closest_approach = f64::INFINITY;
 
-    loop {
+    for _ in 0..config.max_ray_steps {
         let radius = object.distance_to(current_point, t);
-        ray_len += radius;
-        iterations += 1;
-        current_point = point + ray_len * dir;
         if radius < HIT_THRESHOLD {
-            return Some(RayResult {
+            return RayResult {
+                outcome: RayOutcome::Hit,
                 len: ray_len,
                 hit_point: current_point,
-            });
+                closest_approach: 0.0,
+            };
+        }
+        if ray_len > 0.0 {
+            closest_approach = closest_approach.min(radius / ray_len);
         }
 
-        // if iterations > MAX_STEPS {
-        //     return None;
-        // }
+        // f64::max ignores NaN, so a NaN estimate still moves the ray forward by the minimum step
+        ray_len += radius.max(config.min_step_size);
+        current_point = point + ray_len * dir;
 
-        if current_point.x.abs() > backplanes.x ||
+        if ray_len > max_len ||
+            current_point.x.abs() > backplanes.x ||
             current_point.y.abs() > backplanes.y ||
             current_point.z.abs() > backplanes.z {
-            return None;
+            return RayResult {
+                outcome: RayOutcome::Escaped,
+                len: ray_len,
+                hit_point: current_point,
+                closest_approach,
+            };
         }
     }
-}
\ No newline at end of file
+
+    RayResult {
+        outcome: RayOutcome::MaxSteps,
+        len: ray_len,
+        hit_point: current_point,
+        closest_approach,
+    }
+}
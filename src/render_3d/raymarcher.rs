@@ -1,12 +1,23 @@
 use crate::vec3::Vec3;
-use super::ray::cast_ray;
-use super::scene_object::{Sphere, SceneObject};
-use super::fractals::Julia;
-use cgmath::Quaternion;
+use super::camera::{Camera, CubeFace, CubeLayout, Projection};
+use super::light::{Incidence, Light, LightKind};
+use super::material::{Material, Transparency};
+use super::ray::{cast_ray, RayOutcome};
+use super::sampling::Rng;
+use super::scene_object::SceneObject;
+use super::shading::ShadingModel;
+use super::stereo::Stereo;
 use rayon::prelude::*;
-use std::path::Path;
-use image::{DynamicImage, GenericImage, ImageFormat, Rgba};
-use std::process::exit;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use image::{DynamicImage, GenericImage, ImageFormat, ImageResult};
+use serde::Deserialize;
+
+/// Distance to back off from a surface along its normal before casting secondary rays, so they don't immediately
+/// hit the surface they start on
+pub(super) const SURFACE_OFFSET: f64 = 1E-3;
+/// Distance between the samples taken along the normal for ambient occlusion
+const AO_SAMPLE_SPACING: f64 = 0.02;
 
 pub struct RayMarcher<O: SceneObject> {
     pub object: O,
@@ -21,145 +32,390 @@ impl<O: SceneObject> RayMarcher<O> {
             .skip(row * width)
             .take(width)
             .for_each(|(i, pix)| {
-                *pix = self.send_pixel_ray(i, (width, height), t).into()
+                *pix = self.send_pixel_ray(&self.config.camera, i, (width, height), t).into()
             });
     }
 
-    fn send_pixel_ray(&self, buffer_idx: usize, (width, height): (usize, usize), t: f64) -> Vec3 {
+    fn send_pixel_ray(&self, camera: &Camera, buffer_idx: usize, (width, height): (usize, usize), t: f64) -> Vec3 {
         let x = buffer_idx % width;
         let y = buffer_idx / width;
 
+        if self.config.integrator == Integrator::Path {
+            // every sample lands somewhere random in the pixel, which anti-aliases it too
+            let samples = self.config.samples_per_pixel;
+            let mut pixel_sum = Vec3::default();
+            for sample in 0..samples {
+                let mut rng = Rng::for_sample(self.config.seed, buffer_idx, sample);
+                let pixel_sample = (x as f64 + rng.next_f64(), y as f64 + rng.next_f64());
+                let lens_sample = (rng.next_f64(), rng.next_f64());
+                let ray = camera.lens_ray(pixel_sample.0, pixel_sample.1, (width, height), lens_sample);
+                if let Some((origin, ray_dir)) = ray {
+                    pixel_sum = pixel_sum + self.path_trace(origin, ray_dir, t, &mut rng);
+                }
+            }
+            return (1.0 / samples as f64) * pixel_sum;
+        }
+
         let aa_level = self.config.anti_aliasing_level;
 
         let subpixel_size = 1.0 / aa_level as f64;
         let mut pixel_sum = Vec3::default();
         for subpixel_x in 0..aa_level {
             for subpixel_y in 0..aa_level {
-                let ray_dir = Self::camera_ray_dir(
+                // each subpixel looks through a different random point on the lens
+                let mut rng = Rng::for_sample(self.config.seed, buffer_idx, subpixel_x * aa_level + subpixel_y);
+                let ray = camera.lens_ray(
                     x as f64 + subpixel_x as f64 * subpixel_size,
                     y as f64 + subpixel_y as f64 * subpixel_size,
-                    self.config.camera_pos,
-                    self.config.look_at,
-                    self.config.camera_zoom,
                     (width, height),
+                    (rng.next_f64(), rng.next_f64()),
                 );
-                pixel_sum = pixel_sum + self.trace(self.config.camera_pos, ray_dir, t);
+                // parts of the image outside the projection are left black
+                if let Some((origin, ray_dir)) = ray {
+                    pixel_sum = pixel_sum + self.trace(origin, ray_dir, t, 0);
+                }
             }
         }
         (1.0 / (aa_level * aa_level) as f64) * pixel_sum
     }
 
-    fn trace(&self, point: Vec3, dir: Vec3, t: f64) -> Vec3 {
-        let res = cast_ray(&self.object, point, dir, self.config.backplane_positions, t);
+    /// Color seen along a ray. `depth` is the number of reflections and refractions the ray has already been through.
+    fn trace(&self, point: Vec3, dir: Vec3, t: f64, depth: u32) -> Vec3 {
+        let res = cast_ray(&self.object, point, dir, f64::INFINITY, t, &self.config);
         let normal_backoff_dist = 1E-7;
-        match res {
-            Some(res) => {
-                let len = (res.hit_point - self.config.camera_pos).magnitude();
-                let len = (len / 2.0).powi(2);
-                // return (len, len, len).into();
-                // return self.object.get_color(t);
-
-                // if there is a ray hit, do Phong lighting calculations
-                let light_vec = (self.config.light_pos - res.hit_point).normalized();
+        match res.outcome {
+            RayOutcome::Hit => {
+                // if there is a ray hit, shade it with the configured model for each light
                 let norm_point = res.hit_point - normal_backoff_dist * dir;
                 let norm = self.object.normal(norm_point, t);
-                let s_dot_n = norm.dot(light_vec);
-                // return norm;
+                let view_vec = -dir.normalized();
+                let material = self.object.material_at(res.hit_point, norm, t);
+                let shadow_origin = res.hit_point + SURFACE_OFFSET * norm;
+
+                let mut ambient = Vec3::default();
+                let mut diffuse = Vec3::default();
+                let mut specular = Vec3::default();
+                for light in &self.config.lights {
+                    let incidence = match light.incidence(res.hit_point) {
+                        Some(incidence) => incidence,
+                        None => {
+                            ambient = ambient + light.ambient(norm);
+                            continue;
+                        }
+                    };
+
+                    // clamped so surfaces facing away from the light are left with just the ambient term
+                    let s_dot_n = norm.dot(incidence.dir).max(0.0);
+                    if s_dot_n == 0.0 || incidence.radiance.max_component() <= 0.0 {
+                        continue;
+                    }
+
+                    let reflectance = self.config.shading.reflectance(&material, norm, view_vec, incidence.dir);
+                    let shadow = self.shadow(shadow_origin, &incidence, t);
+                    diffuse = diffuse + shadow * (reflectance.diffuse * incidence.radiance);
+                    specular = specular + shadow * (reflectance.specular * incidence.radiance);
+                }
+
+                // rough surfaces scatter their reflections instead of mirroring the scene
+                let reflectivity = material.reflectivity * (1.0 - material.roughness.clamp(0.0, 1.0));
+                let out_of_bounces = depth >= self.config.max_bounces;
+                if let (Some(transparency), false) = (material.transparency, out_of_bounces) {
+                    // transparent surfaces show what is behind and in front of them instead of their own color
+                    let reflectivity = reflectivity.max(ior_reflectivity(transparency.ior));
+                    let fresnel = schlick_fresnel(reflectivity, view_vec.dot(norm));
+                    let reflected = self.trace(shadow_origin, dir.reflect(norm).normalized(), t, depth + 1);
+                    let refracted_dir = dir.normalized().refract(norm, 1.0 / transparency.ior);
+                    let inside_start = res.hit_point - SURFACE_OFFSET * norm;
+                    let transmitted = self.trace_inside(inside_start, refracted_dir, &transparency, t, depth + 1);
+                    return transmitted.lerp(reflected, fresnel) + specular + material.emission;
+                }
+
+                // occlusion is only worth sampling if some ambient light is present
+                if ambient.max_component() > 0.0 {
+                    ambient = self.ambient_occlusion(res.hit_point, norm, t) * ambient;
+                }
+
+                let local = (ambient + diffuse) * material.albedo + specular + material.emission;
+
+                if reflectivity <= 0.0 || out_of_bounces {
+                    return local;
+                }
+                let fresnel = schlick_fresnel(reflectivity, view_vec.dot(norm));
+                let reflected = self.trace(shadow_origin, dir.reflect(norm).normalized(), t, depth + 1);
+                local.lerp(reflected, fresnel)
+            }
+            RayOutcome::Escaped => self.config.background_color,
+            RayOutcome::MaxSteps => self.config.max_steps_color.unwrap_or(self.config.background_color),
+        }
+    }
+
+    /// Color seen along a ray travelling through the inside of a transparent object, from `point` just inside its
+    /// surface. The ray is marched through the inside out object to where it leaves, tinted by the distance it
+    /// travelled and refracted back out. Rays that can't leave because of total internal reflection bounce around
+    /// inside until they run out of bounces.
+    fn trace_inside(&self, point: Vec3, dir: Vec3, transparency: &Transparency, t: f64, depth: u32) -> Vec3 {
+        let res = cast_ray(&Inside(&self.object), point, dir, f64::INFINITY, t, &self.config);
+        let absorption = transparency.absorption(res.len);
+        let exit_color = match res.outcome {
+            RayOutcome::Hit => {
+                // facing back into the object, towards the ray
+                let norm = -self.object.normal(res.hit_point, t);
+                let refracted_dir = dir.normalized().refract(norm, transparency.ior);
+                if refracted_dir != Vec3::default() {
+                    let outside_start = res.hit_point - SURFACE_OFFSET * norm;
+                    self.trace(outside_start, refracted_dir, t, depth + 1)
+                } else if depth < self.config.max_bounces {
+                    let inside_start = res.hit_point + SURFACE_OFFSET * norm;
+                    self.trace_inside(inside_start, dir.reflect(norm), transparency, t, depth + 1)
+                } else {
+                    Vec3::default()
+                }
+            }
+            RayOutcome::Escaped => self.config.background_color,
+            RayOutcome::MaxSteps => self.config.max_steps_color.unwrap_or(self.config.background_color),
+        };
+        absorption * exit_color
+    }
 
-                //specularity
-                let reflect_vec = (-light_vec).reflect(norm).normalized();
-                let view_vec = (self.config.camera_pos - res.hit_point).normalized();
-                let r_dot_v = reflect_vec.dot(view_vec.normalized());
-                let specular_term = r_dot_v.powf(self.config.specular_shininess);
-                let specular_term = if r_dot_v > 0.0 { specular_term } else { 0.0 };
+    /// Fraction of the light reaching a point, from 0 (fully shadowed) to 1 (fully lit).
+    fn shadow(&self, point: Vec3, incidence: &Incidence, t: f64) -> f64 {
+        if self.config.shadows == ShadowMode::Off {
+            return 1.0;
+        }
 
-                s_dot_n * self.object.get_color(t) + specular_term * self.config.specular_color
+        let res = cast_ray(&self.object, point, incidence.dir, incidence.distance, t, &self.config);
+        match (self.config.shadows, res.outcome) {
+            (_, RayOutcome::Hit) => 0.0,
+            (ShadowMode::Soft, _) => {
+                // the closer the shadow ray passed to the object, the deeper into the penumbra the point is
+                let lit = (res.closest_approach / self.config.shadow_softness).clamp(0.0, 1.0);
+                lit * lit * (3.0 - 2.0 * lit)
             }
-            None => self.config.background_color
+            _ => 1.0,
         }
     }
 
-    fn camera_ray_dir(x: f64, y: f64, cam_pos: Vec3, look_at: Vec3, zoom: f64, (width, height): (usize, usize)) -> Vec3 {
-        let u = -(x as f64 / width as f64 * 2.0 - 1.0);
-        let v = y as f64 / height as f64 * 2.0 - 1.0;
+    /// Fraction of ambient light reaching a point, from 0 (fully occluded) to 1 (fully open). Samples the distance
+    /// field at points stepping out along the normal: if the surface were flat and open each sample would be as far
+    /// from the object as it is from the point, and any shortfall means something nearby is blocking the light.
+    fn ambient_occlusion(&self, point: Vec3, normal: Vec3, t: f64) -> f64 {
+        let mut occlusion = 0.0;
+        let mut max_occlusion = 0.0;
+        let mut weight = 1.0;
+        for i in 1..=self.config.ao_samples {
+            let sample_dist = AO_SAMPLE_SPACING * i as f64;
+            let dist = self.object.distance_to(point + sample_dist * normal, t);
+            occlusion += weight * (sample_dist - dist).max(0.0);
+            max_occlusion += weight * sample_dist;
+            // samples further out say less about the crevice the point is in
+            weight *= 0.5;
+        }
+        if max_occlusion == 0.0 {
+            return 1.0;
+        }
+        (1.0 - self.config.ao_strength * occlusion / max_occlusion).clamp(0.0, 1.0)
+    }
 
-        let look_dir = (look_at - cam_pos).normalized();
-        let right_vec = Vec3::from((0, -1, 0)).cross(look_dir).normalized();
-        let down_vec = look_dir.cross(right_vec).normalized();
+    /// Render the scene to a PNG file, returning the paths written. A cube map with the faces layout is written as
+    /// six images, with the name of each face added to the file name.
+    pub fn render_to_image(&self, file: &Path, size: (usize, usize), t: f64) -> ImageResult<Vec<PathBuf>> {
+        let camera = &self.config.camera;
+        if camera.projection != Projection::Cube || camera.cube_layout != CubeLayout::Faces {
+            self.render_camera_to_image(camera, file, size, t)?;
+            return Ok(vec![file.to_path_buf()]);
+        }
 
-        let zoomed_cam_pos = cam_pos + zoom * look_dir;
-        let pix_pos = zoomed_cam_pos + u * right_vec + v * down_vec;
-        let dir = pix_pos - cam_pos;
-        dir.normalized()
+        let mut paths = Vec::new();
+        for &face in CubeFace::ALL.iter() {
+            let face_camera = Camera { cube_face: Some(face), ..*camera };
+            let path = face_path(file, face);
+            self.render_camera_to_image(&face_camera, &path, size, t)?;
+            paths.push(path);
+        }
+        Ok(paths)
     }
 
-    fn render_to_image(&self, file: &Path, (width, height): (usize, usize), t: f64) {
+    /// Render the view from `camera`, or both eyes' views put together in stereo, to a PNG file.
+    fn render_camera_to_image(&self, camera: &Camera, file: &Path, size: (usize, usize), t: f64) -> ImageResult<()> {
+        let (buf, (width, height)) = match self.config.stereo {
+            Some(stereo) => {
+                let (left, right) = stereo.eyes(camera);
+                let left = self.render_pixels(&left, size, t);
+                let right = self.render_pixels(&right, size, t);
+                stereo.layout.compose(&left, &right, size)
+            }
+            None => (self.render_pixels(camera, size, t), size),
+        };
+
         let mut image = DynamicImage::new_rgb8(
             width as u32,
             height as u32,
         );
 
-        let mut buf = vec![Rgba([0, 0, 0, 0]); width * height];
+        // copy buffer to image
+        for (i, pixel) in buf.into_iter().enumerate() {
+            let x = (i % width) as u32;
+            let y = (i / width) as u32;
+            image.put_pixel(x, y, pixel.into());
+        }
+
+        image.save_with_format(file, ImageFormat::Png)
+    }
+
+    /// Colors of every pixel of the view from `camera`, row by row.
+    fn render_pixels(&self, camera: &Camera, (width, height): (usize, usize), t: f64) -> Vec<Vec3> {
+        let mut buf = vec![Vec3::default(); width * height];
         buf.par_iter_mut().enumerate().for_each(|(i, y)| {
             *y = self.send_pixel_ray(
+                camera,
                 i,
                 (width, height),
                 t,
-            ).into();
+            );
         });
+        buf
+    }
 
-        // copy buffer to image
-        for i in 0..buf.len() {
-            let x = (i % width) as u32;
-            let y = (i / width) as u32;
-            image.put_pixel(x, y, buf[i]);
+    /// Render frame `i` of an animation.
+    pub fn render_frame<F: Fn(u32) -> String>(&self, config: &ImageRenderConfiguration<F>, i: u32) -> ImageResult<()> {
+        // t is computed from the frame index rather than accumulated, so float error can't add or drop a frame
+        let t = config.t_start + i as f64 * config.t_step;
+        let image_name = (config.image_name)(i);
+        let path = Path::new(&image_name);
+        for written in self.render_to_image(path, (config.width, config.height), t)? {
+            println!("rendered image {} (t = {})", written.display(), t);
         }
-
-        image.save_with_format(file, ImageFormat::Png)
-            .expect("could not save image");
+        Ok(())
     }
+}
 
-    pub fn render_images<F: Fn(u32) -> String>(&self, config: ImageRenderConfiguration<F>) {
-        let mut t = config.t_start;
-        let mut i = 0u32;
+/// `file` with the name of a cube map face added before its extension, eg. sky.png becomes sky_front.png.
+fn face_path(file: &Path, face: CubeFace) -> PathBuf {
+    let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match file.extension() {
+        Some(extension) => format!("{}_{}.{}", stem, face.name(), extension.to_string_lossy()),
+        None => format!("{}_{}", stem, face.name()),
+    };
+    file.with_file_name(name)
+}
+
+/// Reflectivity looking straight on at the boundary between air and a material with the given index of refraction.
+pub(super) fn ior_reflectivity(ior: f64) -> f64 {
+    ((ior - 1.0) / (ior + 1.0)).powi(2)
+}
 
-        while t < config.t_stop {
-            let image_name = (config.image_name)(i);
-            let path = Path::new(&image_name);
-            self.render_to_image(path, (config.width, config.height), t);
+/// Turns an object inside out, so a ray starting inside it can be marched to the point where it leaves.
+pub(super) struct Inside<'a, O: SceneObject>(pub(super) &'a O);
 
-            i += 1;
-            t += config.t_step;
-            println!("rendered image {}", image_name);
-        }
+impl<'a, O: SceneObject> SceneObject for Inside<'a, O> {
+    fn distance_to(&self, point: Vec3, t: f64) -> f64 {
+        -self.0.distance_to(point, t)
     }
+
+    fn get_material(&self, t: f64) -> Material {
+        self.0.get_material(t)
+    }
+}
+
+/// Schlick's approximation of how much light a surface reflects, given its reflectivity looking straight on and the
+/// cosine of the angle between the view direction and the normal.
+pub(super) fn schlick_fresnel(reflectivity: f64, cos_theta: f64) -> f64 {
+    reflectivity + (1.0 - reflectivity) * (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5)
 }
 
 #[derive(Debug)]
 pub struct RayMarcherConfig {
-    pub camera_pos: Vec3,
-    pub look_at: Vec3,
-    pub light_pos: Vec3,
+    pub camera: Camera,
+    /// Every light in the scene, their contributions are summed
+    pub lights: Vec<Light>,
     pub background_color: Vec3,
-    pub camera_zoom: f64,
     pub anti_aliasing_level: u32,
     pub backplane_positions: Vec3,
-    pub specular_shininess: f64,
-    pub specular_color: Vec3,
+    /// How surfaces scatter the light that reaches them
+    pub shading: ShadingModel,
+    /// Rays that take more than this many steps are given up on
+    pub max_ray_steps: u32,
+    /// Smallest distance a ray is advanced by each step, so NaN or negative estimates can't stall it
+    pub min_step_size: f64,
+    /// Color of rays that ran out of steps. Uses the background color if not set
+    pub max_steps_color: Option<Vec3>,
+    pub shadows: ShadowMode,
+    /// Width of soft shadow penumbras, as the tangent of the cone around a shadow ray that is partially lit
+    pub shadow_softness: f64,
+    /// How strongly crevices block ambient light. 0 disables ambient occlusion
+    pub ao_strength: f64,
+    /// Number of distance samples taken along the normal for ambient occlusion
+    pub ao_samples: u32,
+    /// How many times a ray can be reflected or refracted before the last surface it hits is treated as opaque and
+    /// not reflective
+    pub max_bounces: u32,
+    pub integrator: Integrator,
+    /// Number of paths traced through each pixel by the path integrator
+    pub samples_per_pixel: u32,
+    /// Seed for the random numbers of the path integrator and depth of field. Renders with the same seed come out the
+    /// same
+    pub seed: u64,
+    /// Renders a stereo pair instead of a single view. Only used when rendering to images
+    pub stereo: Option<Stereo>,
 }
 
 impl Default for RayMarcherConfig {
     fn default() -> Self {
         RayMarcherConfig {
-            camera_pos: Vec3 { x: 2.0, y: 4.0, z: 4.0 },
-            look_at: Vec3 { x: 0.0, y: 0.0, z: 0.0 },
-            light_pos: Vec3 { x: 2.0, y: 4.0, z: 4.0 },
+            camera: Camera::default(),
+            lights: vec![
+                Light {
+                    kind: LightKind::Point { position: Vec3 { x: 2.0, y: 4.0, z: 4.0 } },
+                    color: Vec3 { x: 1.0, y: 1.0, z: 1.0 },
+                    intensity: 1.0,
+                    falloff: false,
+                },
+                Light {
+                    kind: LightKind::Ambient,
+                    color: Vec3 { x: 0.1, y: 0.1, z: 0.1 },
+                    intensity: 1.0,
+                    falloff: false,
+                },
+            ],
             background_color: Vec3 { x: 0.0, y: 0.0, z: 0.0 },
-            camera_zoom: 3.0,
             anti_aliasing_level: 4u32,
             backplane_positions: Vec3 { x: 3.0, y: 3.0, z: 3.0 },
-            specular_shininess: 50.0,
-            specular_color: Vec3 { x: 1.0, y: 1.0, z: 1.0 },
+            shading: ShadingModel::Phong,
+            max_ray_steps: 200,
+            min_step_size: 1E-5,
+            max_steps_color: None,
+            shadows: ShadowMode::Off,
+            shadow_softness: 0.05,
+            ao_strength: 1.0,
+            ao_samples: 5,
+            max_bounces: 3,
+            integrator: Integrator::Whitted,
+            samples_per_pixel: 16,
+            seed: 0,
+            stereo: None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShadowMode {
+    Off,
+    /// Points are either fully lit or fully shadowed
+    Hard,
+    /// Shadows get a penumbra based on how closely the shadow ray passes the object
+    Soft,
+}
+
+impl FromStr for ShadowMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(ShadowMode::Off),
+            "hard" => Ok(ShadowMode::Hard),
+            "soft" => Ok(ShadowMode::Soft),
+            _ => Err(format!("unknown shadow mode {}", s)),
         }
     }
 }
@@ -168,7 +424,30 @@ pub struct ImageRenderConfiguration<F: Fn(u32) -> String> {
     pub width: usize,
     pub height: usize,
     pub t_start: f64,
-    pub t_stop: f64,
     pub t_step: f64,
+    pub frames: u32,
     pub image_name: F,
-}
\ No newline at end of file
+}
+
+/// How the light reaching the camera is worked out.
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Integrator {
+    /// Direct lighting from each light, with ambient occlusion standing in for indirect light, plus mirror
+    /// reflections and refractions
+    Whitted,
+    /// Monte Carlo path tracing with global illumination. Noisy, converging as the samples per pixel go up
+    Path,
+}
+
+impl FromStr for Integrator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "whitted" => Ok(Integrator::Whitted),
+            "path" => Ok(Integrator::Path),
+            _ => Err(format!("unknown integrator {}", s)),
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use crate::vec3::Vec3;
+use std::f64::consts::PI;
+
+/// Small deterministic random number generator (xorshift64*). Each sample gets its own generator seeded from the
+/// render seed and the sample's position, so images come out the same no matter how rayon schedules the pixels.
+pub struct Rng(u64);
+
+impl Rng {
+    /// Generator for one sample of one pixel.
+    pub fn for_sample(seed: u64, pixel: usize, sample: u32) -> Self {
+        let state = splitmix(splitmix(splitmix(seed) ^ pixel as u64) ^ sample as u64);
+        // xorshift gets stuck at zero
+        Rng(if state == 0 { 0x9E37_79B9_7F4A_7C15 } else { state })
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Uniform in [0, 1).
+    pub fn next_f64(&mut self) -> f64 {
+        // the top 53 bits fill the mantissa exactly
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// SplitMix64 finalizer, to scramble seeds that differ in only a few bits into unrelated states.
+fn splitmix(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Random direction in the hemisphere around `normal`, more likely the closer it is to the normal (with a density of
+/// cos(theta) / pi). Scattering a Lambert surface's light this way cancels the cosine term of the rendering equation.
+pub fn cosine_hemisphere(normal: Vec3, rng: &mut Rng) -> Vec3 {
+    let r = rng.next_f64().sqrt();
+    let phi = 2.0 * PI * rng.next_f64();
+    let (tangent, bitangent) = orthonormal_basis(normal);
+    let height = (1.0 - r * r).max(0.0).sqrt();
+    ((r * phi.cos()) * tangent + (r * phi.sin()) * bitangent + height * normal).normalized()
+}
+
+/// Random direction around `axis`, with a density of (exponent + 1) / 2pi * cos(theta)^exponent in its angle theta
+/// from the axis. Higher exponents bunch the directions up around the axis, like a Phong highlight around the mirror
+/// direction.
+pub fn power_cosine(axis: Vec3, exponent: f64, rng: &mut Rng) -> Vec3 {
+    let cos_theta = rng.next_f64().powf(1.0 / (exponent + 1.0));
+    around(axis, cos_theta, rng)
+}
+
+/// Density `power_cosine` picks `dir` with.
+pub fn power_cosine_pdf(axis: Vec3, exponent: f64, dir: Vec3) -> f64 {
+    let cos_theta = axis.dot(dir);
+    if cos_theta <= 0.0 {
+        0.0
+    } else {
+        (exponent + 1.0) / (2.0 * PI) * cos_theta.powf(exponent)
+    }
+}
+
+/// Random microfacet normal around `normal` for the GGX distribution of width `alpha`, with a density of
+/// D(h) * cos(theta_h).
+pub fn ggx_half_vector(normal: Vec3, alpha: f64, rng: &mut Rng) -> Vec3 {
+    let u = rng.next_f64();
+    let cos_theta = ((1.0 - u) / (1.0 + (alpha * alpha - 1.0) * u)).sqrt();
+    around(normal, cos_theta, rng)
+}
+
+/// Direction at an angle with cosine `cos_theta` from `axis`, turned a random way around it.
+fn around(axis: Vec3, cos_theta: f64, rng: &mut Rng) -> Vec3 {
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * rng.next_f64();
+    let (tangent, bitangent) = orthonormal_basis(axis);
+    ((sin_theta * phi.cos()) * tangent + (sin_theta * phi.sin()) * bitangent + cos_theta * axis).normalized()
+}
+
+/// Two unit vectors perpendicular to `normal` and to each other.
+fn orthonormal_basis(normal: Vec3) -> (Vec3, Vec3) {
+    // cross with whichever axis is furthest from parallel to the normal
+    let axis = if normal.x.abs() < 0.9 { Vec3::from((1, 0, 0)) } else { Vec3::from((0, 1, 0)) };
+    let tangent = normal.cross(axis).normalized();
+    (tangent, normal.cross(tangent))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let mut a = Rng::for_sample(7, 12, 3);
+        let mut b = Rng::for_sample(7, 12, 3);
+        let mut c = Rng::for_sample(7, 12, 4);
+        let a: Vec<u64> = (0..8).map(|_| a.next_u64()).collect();
+        let b: Vec<u64> = (0..8).map(|_| b.next_u64()).collect();
+        let c: Vec<u64> = (0..8).map(|_| c.next_u64()).collect();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn cosine_hemisphere_stays_above_surface() {
+        let normal = Vec3::from((1.0, 2.0, -0.5)).normalized();
+        let mut rng = Rng::for_sample(0, 0, 0);
+        let mut mean_cos = 0.0;
+        let samples = 10000;
+        for _ in 0..samples {
+            let dir = cosine_hemisphere(normal, &mut rng);
+            assert!((dir.magnitude() - 1.0).abs() < 1E-9);
+            assert!(dir.dot(normal) >= 0.0);
+            mean_cos += dir.dot(normal) / samples as f64;
+        }
+        // the mean of cos(theta) under a cos(theta) / pi density is 2/3
+        assert!((mean_cos - 2.0 / 3.0).abs() < 0.01, "mean cosine was {}", mean_cos);
+    }
+}
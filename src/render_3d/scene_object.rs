@@ -1,3 +1,4 @@
+use super::material::Material;
 use crate::vec3::Vec3;
 
 const EPS: f64 = 1E-7;
@@ -5,7 +6,13 @@ const EPS: f64 = 1E-7;
 // T is the varied parameter
 pub trait SceneObject: Send + Sync {
     fn distance_to(&self, point: Vec3, t: f64) -> f64;
-    fn get_color(&self, t: f64) -> Vec3;
+    fn get_material(&self, t: f64) -> Material;
+    /// Material of the surface at a point with the given (world space) normal. Defaults to the object's single
+    /// material. Compound objects override this to pick the material of the operand nearest the point, and wrappers
+    /// to map the point and normal into the wrapped object's space.
+    fn material_at(&self, _point: Vec3, _normal: Vec3, t: f64) -> Material {
+        self.get_material(t)
+    }
     fn normal(&self, p: Vec3, t: f64) -> Vec3 {
         let x_plus = self.distance_to((p.x + EPS, p.y, p.z).into(), t);
         let x_minus = self.distance_to((p.x - EPS, p.y, p.z).into(), t);
@@ -25,23 +32,49 @@ pub trait SceneObject: Send + Sync {
     }
 }
 
+impl SceneObject for Box<dyn SceneObject> {
+    fn distance_to(&self, point: Vec3, t: f64) -> f64 {
+        (**self).distance_to(point, t)
+    }
+
+    fn get_material(&self, t: f64) -> Material {
+        (**self).get_material(t)
+    }
+
+    fn material_at(&self, point: Vec3, normal: Vec3, t: f64) -> Material {
+        (**self).material_at(point, normal, t)
+    }
+
+    fn normal(&self, p: Vec3, t: f64) -> Vec3 {
+        (**self).normal(p, t)
+    }
+}
+
 pub struct Sphere {
     pub center: Vec3,
     pub radius: f64,
-    pub color: Vec3,
+    pub material: Material,
 }
 
 impl SceneObject for Sphere {
     fn distance_to(&self, point: Vec3, _: f64) -> f64 {
-        let point = Vec3 {
-            x: point.x % 5.0,
-            y: point.y,
-            z: point.z
-        };
         (point - self.center).magnitude() - self.radius
     }
 
-    fn get_color(&self, _: f64) -> Vec3 {
-        self.color
+    fn get_material(&self, _: f64) -> Material {
+        self.material
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sphere_distance() {
+        let sphere = Sphere { center: (1, 0, 0).into(), radius: 0.5, material: Material::default() };
+        assert_eq!(sphere.distance_to((3, 0, 0).into(), 0.0), 1.5);
+        assert_eq!(sphere.distance_to((1, 0, 0).into(), 0.0), -0.5);
+        // no repetition along x
+        assert_eq!(sphere.distance_to((6, 0, 0).into(), 0.0), 4.5);
+    }
+}
@@ -1,3 +1,4 @@
+use super::material::Material;
 use super::scene_object::SceneObject;
 use crate::vec3::Vec3;
 
@@ -18,8 +19,12 @@ impl<O: SceneObject> SceneObject for ZSectioned<O> {
         }
     }
 
-    fn get_color(&self, t: f64) -> Vec3 {
-        self.object.get_color(t)
+    fn get_material(&self, t: f64) -> Material {
+        self.object.get_material(t)
+    }
+
+    fn material_at(&self, point: Vec3, normal: Vec3, t: f64) -> Material {
+        self.object.material_at(point, normal, t)
     }
 
     fn normal(&self, p: Vec3, t: f64) -> Vec3 {
@@ -0,0 +1,325 @@
+use super::material::Material;
+use super::sampling::{cosine_hemisphere, ggx_half_vector, power_cosine, power_cosine_pdf, Rng};
+use crate::vec3::Vec3;
+use serde::Deserialize;
+use std::f64::consts::PI;
+use std::str::FromStr;
+
+/// The GGX width (roughness squared) is clamped to at least this much for the physically based model, whose highlights
+/// shrink to nothing on a perfectly smooth surface, and whose geometry term divides by zero there.
+const MIN_PBR_ALPHA: f64 = 0.0025;
+/// Fraction of light a dielectric (non-metal) reflects looking straight on, roughly right for most plastics, glass
+/// and stone.
+const DIELECTRIC_REFLECTANCE: f64 = 0.04;
+
+/// How light arriving at a surface from a light is scattered towards the viewer.
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShadingModel {
+    /// Lambert diffuse, with highlights from how closely the mirrored light direction lines up with the view
+    Phong,
+    /// Lambert diffuse, with highlights from how closely the normal lines up with the vector halfway between the
+    /// light and the view. Highlights are broader than Phong's at the same shininess, and stay round at grazing angles
+    Blinn,
+    /// Metallic/roughness model with GGX (Cook-Torrance) highlights. Light the highlight reflects is taken away from
+    /// the diffuse part, so a surface never reflects more light than it receives
+    Pbr,
+}
+
+/// Light scattered towards the viewer per unit of light arriving along the light direction. The diffuse part still
+/// has to be multiplied by the material's albedo.
+pub struct Reflectance {
+    pub diffuse: Vec3,
+    pub specular: Vec3,
+}
+
+impl ShadingModel {
+    /// `normal`, `view` (towards the viewer) and `light` (towards the light) must be normalized, and the light must
+    /// be above the surface. Every model scatters a full unit of diffuse light from a white surface facing straight
+    /// at the light, so scenes keep their brightness when switching between them.
+    pub fn reflectance(self, material: &Material, normal: Vec3, view: Vec3, light: Vec3) -> Reflectance {
+        let n_dot_l = normal.dot(light).max(0.0);
+        match self {
+            ShadingModel::Phong => {
+                let r_dot_v = (-light).reflect(normal).normalized().dot(view);
+                Reflectance {
+                    diffuse: n_dot_l.into(),
+                    specular: r_dot_v.max(0.0).powf(material.shininess) * material.specular_color,
+                }
+            }
+            ShadingModel::Blinn => {
+                let n_dot_h = normal.dot((light + view).normalized());
+                Reflectance {
+                    diffuse: n_dot_l.into(),
+                    specular: n_dot_h.max(0.0).powf(material.shininess) * material.specular_color,
+                }
+            }
+            ShadingModel::Pbr => pbr_reflectance(material, normal, view, light, n_dot_l),
+        }
+    }
+
+    /// Random direction for a path to continue in after scattering off the surface towards `view`, and the weight of
+    /// the light arriving back along it. The direction comes from either the diffuse part or the highlight, picked at
+    /// random in proportion to roughly how much light each scatters, and the weight is the reflectance divided by the
+    /// combined density of both, so on average the bounce lights the surface the same way `reflectance` would.
+    /// `None` if the direction ends up below the surface, which ends the path.
+    pub fn scatter(self, material: &Material, normal: Vec3, view: Vec3, rng: &mut Rng) -> Option<(Vec3, Vec3)> {
+        let (diffuse_weight, specular_weight) = match self {
+            ShadingModel::Pbr => {
+                let metallic = material.metallic.clamp(0.0, 1.0);
+                ((1.0 - metallic) * material.albedo.max_component(), pbr_f0(material).max_component())
+            }
+            _ => (material.albedo.max_component(), material.specular_color.max_component()),
+        };
+        if diffuse_weight + specular_weight <= 0.0 {
+            return None;
+        }
+        let specular_chance = specular_weight / (diffuse_weight + specular_weight);
+
+        let light = if rng.next_f64() < specular_chance {
+            self.sample_specular(material, normal, view, rng)?
+        } else {
+            cosine_hemisphere(normal, rng)
+        };
+        let n_dot_l = normal.dot(light);
+        if n_dot_l <= 0.0 {
+            return None;
+        }
+
+        let pdf = (1.0 - specular_chance) * n_dot_l / PI
+            + specular_chance * self.specular_pdf(material, normal, view, light);
+        let reflectance = self.reflectance(material, normal, view, light);
+        // undo the pi scaling of the reflectance along with dividing by the density
+        Some((light, (1.0 / (PI * pdf)) * (reflectance.diffuse * material.albedo + reflectance.specular)))
+    }
+
+    /// Light direction picked around the highlight, or `None` if the picked microfacet faces away from the viewer.
+    fn sample_specular(self, material: &Material, normal: Vec3, view: Vec3, rng: &mut Rng) -> Option<Vec3> {
+        let half = match self {
+            ShadingModel::Phong => {
+                let mirror = (-view).reflect(normal).normalized();
+                return Some(power_cosine(mirror, material.shininess, rng));
+            }
+            ShadingModel::Blinn => power_cosine(normal, material.shininess, rng),
+            ShadingModel::Pbr => ggx_half_vector(normal, pbr_alpha(material), rng),
+        };
+        if view.dot(half) <= 0.0 {
+            return None;
+        }
+        Some((-view).reflect(half).normalized())
+    }
+
+    /// Density `sample_specular` picks `light` with.
+    fn specular_pdf(self, material: &Material, normal: Vec3, view: Vec3, light: Vec3) -> f64 {
+        let half = (light + view).normalized();
+        // picking the half vector rather than the light direction squashes the density by 4 v.h
+        let half_pdf = match self {
+            ShadingModel::Phong => {
+                let mirror = (-view).reflect(normal).normalized();
+                return power_cosine_pdf(mirror, material.shininess, light);
+            }
+            ShadingModel::Blinn => power_cosine_pdf(normal, material.shininess, half),
+            ShadingModel::Pbr => {
+                let n_dot_h = normal.dot(half);
+                if n_dot_h <= 0.0 {
+                    return 0.0;
+                }
+                ggx_distribution(pbr_alpha(material), n_dot_h) * n_dot_h
+            }
+        };
+        half_pdf / (4.0 * view.dot(half).max(1E-9))
+    }
+}
+
+/// Cook-Torrance highlights with the GGX normal distribution, Smith-Schlick shadowing and Schlick's Fresnel
+/// approximation, over a Lambert diffuse base. Metals have no diffuse part, and tint their highlights with the albedo.
+fn pbr_reflectance(material: &Material, normal: Vec3, view: Vec3, light: Vec3, n_dot_l: f64) -> Reflectance {
+    let half = (light + view).normalized();
+    let n_dot_v = normal.dot(view).max(1E-4);
+    let n_dot_h = normal.dot(half).max(0.0);
+    let v_dot_h = view.dot(half).max(0.0);
+
+    let metallic = material.metallic.clamp(0.0, 1.0);
+    let alpha = pbr_alpha(material);
+
+    let f0 = pbr_f0(material);
+    let fresnel = f0 + (1.0 - v_dot_h).powi(5) * (Vec3::from(1) - f0);
+    let distribution = ggx_distribution(alpha, n_dot_h);
+
+    let k = alpha / 2.0;
+    let geometry = (n_dot_v / (n_dot_v * (1.0 - k) + k)) * (n_dot_l / (n_dot_l * (1.0 - k) + k));
+
+    // the BRDF is scaled by pi, matching a Lambert diffuse of albedo * n.l rather than albedo / pi * n.l
+    let specular = (PI * distribution * geometry / (4.0 * n_dot_v)) * fresnel;
+    let diffuse = ((1.0 - metallic) * n_dot_l) * (Vec3::from(1) - fresnel);
+    Reflectance { diffuse, specular }
+}
+
+/// GGX width of the physically based model's highlights.
+fn pbr_alpha(material: &Material) -> f64 {
+    let roughness = material.roughness.clamp(0.0, 1.0);
+    (roughness * roughness).max(MIN_PBR_ALPHA)
+}
+
+/// Fraction of light the physically based model reflects looking straight on.
+fn pbr_f0(material: &Material) -> Vec3 {
+    (DIELECTRIC_REFLECTANCE * material.specular_color).lerp(material.albedo, material.metallic.clamp(0.0, 1.0))
+}
+
+/// GGX density of microfacets facing along a half vector at an angle with cosine `n_dot_h` from the normal.
+fn ggx_distribution(alpha: f64, n_dot_h: f64) -> f64 {
+    let alpha2 = alpha * alpha;
+    let d_denom = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    alpha2 / (PI * d_denom * d_denom)
+}
+
+impl FromStr for ShadingModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "phong" => Ok(ShadingModel::Phong),
+            "blinn" => Ok(ShadingModel::Blinn),
+            "pbr" => Ok(ShadingModel::Pbr),
+            _ => Err(format!("unknown shading model {}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn white(metallic: f64, roughness: f64) -> Material {
+        Material { albedo: 1.into(), metallic, roughness, ..Material::default() }
+    }
+
+    #[test]
+    fn diffuse_matches_between_models() {
+        let up = Vec3::from((0, 1, 0));
+        let light = Vec3::from((1, 1, 0)).normalized();
+        let material = white(0.0, 0.5);
+        let phong = ShadingModel::Phong.reflectance(&material, up, up, light);
+        let blinn = ShadingModel::Blinn.reflectance(&material, up, up, light);
+        let pbr = ShadingModel::Pbr.reflectance(&material, up, up, light);
+        assert!((phong.diffuse.x - light.y).abs() < 1E-9);
+        assert!((blinn.diffuse.x - light.y).abs() < 1E-9);
+        // a dielectric loses only the few percent its highlight reflects
+        assert!(pbr.diffuse.x < light.y && pbr.diffuse.x > 0.9 * light.y);
+    }
+
+    #[test]
+    fn pbr_metals_have_no_diffuse() {
+        let up = Vec3::from((0, 1, 0));
+        let reflectance = ShadingModel::Pbr.reflectance(&white(1.0, 0.5), up, up, up);
+        assert_eq!(reflectance.diffuse.max_component(), 0.0);
+        assert!(reflectance.specular.max_component() > 0.0);
+    }
+
+    #[test]
+    fn pbr_conserves_energy() {
+        // averaged over the hemisphere of light directions, a white surface can't scatter more light than arrives
+        let up = Vec3::from((0, 1, 0));
+        let view = Vec3::from((0.0, 1.0, 0.5)).normalized();
+        for &roughness in &[0.2, 0.5, 1.0] {
+            for &metallic in &[0.0, 1.0] {
+                let material = white(metallic, roughness);
+                let steps = 200;
+                let mut total = 0.0;
+                for i in 0..steps {
+                    for j in 0..steps {
+                        // uniform sampling of the hemisphere, whose solid angle is 2pi
+                        let cos_theta = (i as f64 + 0.5) / steps as f64;
+                        let phi = 2.0 * PI * (j as f64 + 0.5) / steps as f64;
+                        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+                        let light = Vec3::from((sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin()));
+                        let reflectance = ShadingModel::Pbr.reflectance(&material, up, view, light);
+                        total += (reflectance.diffuse + reflectance.specular).x;
+                    }
+                }
+                // undo the pi scaling of the reflectance to integrate the BRDF times n.l over the hemisphere
+                let albedo = total / (steps * steps) as f64 * 2.0 * PI / PI;
+                assert!(albedo <= 1.0, "roughness {} metallic {} reflected {}", roughness, metallic, albedo);
+            }
+        }
+    }
+
+    /// Fraction of light arriving evenly from the whole sky the surface scatters towards `view`, integrating the
+    /// reflectance over the hemisphere.
+    fn hemisphere_albedo(model: ShadingModel, material: &Material, view: Vec3) -> f64 {
+        let up = Vec3::from((0, 1, 0));
+        let steps = 400;
+        let mut total = 0.0;
+        for i in 0..steps {
+            for j in 0..steps {
+                let cos_theta = (i as f64 + 0.5) / steps as f64;
+                let phi = 2.0 * PI * (j as f64 + 0.5) / steps as f64;
+                let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+                let light = Vec3::from((sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin()));
+                let reflectance = model.reflectance(material, up, view, light);
+                total += (reflectance.diffuse * material.albedo + reflectance.specular).x;
+            }
+        }
+        total / (steps * steps) as f64 * 2.0
+    }
+
+    /// The same fraction, averaged over the weights of scattered paths.
+    fn scattered_albedo(model: ShadingModel, material: &Material, view: Vec3) -> f64 {
+        let up = Vec3::from((0, 1, 0));
+        let samples = 200_000;
+        let mut total = 0.0;
+        for sample in 0..samples {
+            let mut rng = Rng::for_sample(1, 0, sample);
+            if let Some((light, weight)) = model.scatter(material, up, view, &mut rng) {
+                assert!(light.dot(up) > 0.0);
+                total += weight.x;
+            }
+        }
+        total / samples as f64
+    }
+
+    #[test]
+    fn white_diffuse_passes_the_furnace_test() {
+        let up = Vec3::from((0, 1, 0));
+        let view = Vec3::from((0.0, 1.0, 0.5)).normalized();
+        let material = Material { albedo: 1.into(), specular_color: 0.into(), ..Material::default() };
+        let mut rng = Rng::for_sample(0, 0, 0);
+        for _ in 0..1000 {
+            let (_, weight) = ShadingModel::Phong.scatter(&material, up, view, &mut rng).unwrap();
+            assert!((weight.x - 1.0).abs() < 1E-9);
+        }
+    }
+
+    #[test]
+    fn scattering_matches_reflectance() {
+        let view = Vec3::from((0.0, 1.0, 0.8)).normalized();
+        let phong = Material { albedo: 0.5.into(), shininess: 20.0, ..Material::default() };
+        let cases = [
+            (ShadingModel::Phong, phong),
+            (ShadingModel::Blinn, phong),
+            (ShadingModel::Pbr, white(0.0, 0.3)),
+            (ShadingModel::Pbr, white(1.0, 0.3)),
+            (ShadingModel::Pbr, white(1.0, 0.8)),
+        ];
+        for (model, material) in cases.iter() {
+            let expected = hemisphere_albedo(*model, material, view);
+            let scattered = scattered_albedo(*model, material, view);
+            assert!(
+                (scattered - expected).abs() < 0.02 * expected,
+                "{:?} metallic {} scattered {} but reflects {}",
+                model,
+                material.metallic,
+                scattered,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn pbr_metals_scatter_less_than_white_diffuse() {
+        // a metal only bounces light through its highlight, which loses some to shadowing between microfacets
+        let view = Vec3::from((0.0, 1.0, 0.8)).normalized();
+        let metal = scattered_albedo(ShadingModel::Pbr, &white(1.0, 0.8), view);
+        assert!(metal < 0.95, "rough metal scattered {}", metal);
+    }
+}
@@ -0,0 +1,177 @@
+use super::camera::Camera;
+use crate::vec3::Vec3;
+use serde::Deserialize;
+use std::str::FromStr;
+
+/// Stereo pair rendered from two eyes either side of the camera.
+#[derive(Debug, Copy, Clone)]
+pub struct Stereo {
+    /// Interpupillary distance: how far apart the eyes are, in scene units
+    pub ipd: f64,
+    /// Distance along the view direction at which the eyes' views cross. Things there appear at the depth of the
+    /// screen, nearer things in front of it and further things behind it. Defaults to the distance to `look_at`
+    pub convergence: Option<f64>,
+    pub layout: StereoLayout,
+}
+
+/// How the two eyes' images are put together into one.
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StereoLayout {
+    /// Left eye on the left and right eye on the right, making the image twice as wide
+    SideBySide,
+    /// Left eye above the right eye, making the image twice as high
+    TopBottom,
+    /// Both eyes in one image of the original size, for glasses with a red filter over the left eye and a cyan one
+    /// over the right
+    Anaglyph,
+}
+
+/// Dubois' least squares red/cyan anaglyph matrices, which keep more of the original colors and show less of each eye
+/// through the wrong filter than simply taking the red channel from one eye and the others from the other. Rows are
+/// the output's red, green and blue.
+const ANAGLYPH_LEFT: [[f64; 3]; 3] = [
+    [0.456100, 0.500484, 0.176381],
+    [-0.0400822, -0.0378246, -0.0157589],
+    [-0.0152161, -0.0205971, -0.00546856],
+];
+const ANAGLYPH_RIGHT: [[f64; 3]; 3] = [
+    [-0.0434706, -0.0879388, -0.00155529],
+    [0.378476, 0.73364, -0.0184503],
+    [-0.0721527, -0.112961, 1.2264],
+];
+
+impl Stereo {
+    /// Cameras for the left and right eyes of a perspective camera. Each is moved half the interpupillary distance
+    /// sideways and keeps looking in the camera's view direction, with its image shifted so the middle of both images
+    /// is the point on the view direction at the convergence distance. Turning the eyes in to look at that point
+    /// instead would tilt their image planes apart, giving the edges of the images vertical parallax. Both keep the
+    /// camera's focus distance.
+    pub fn eyes(&self, camera: &Camera) -> (Camera, Camera) {
+        let basis = camera.basis();
+        let distance = (camera.look_at - camera.position).magnitude();
+        let convergence = self.convergence.unwrap_or(distance);
+        let half_height = (0.5 * camera.fov).to_radians().tan();
+        let focus_distance = Some(camera.focus_distance.unwrap_or(distance));
+        let eye = |side: f64| {
+            let offset = side * 0.5 * self.ipd;
+            let position = camera.position + offset * basis.right;
+            Camera {
+                position,
+                look_at: position + distance * basis.forward,
+                focus_distance,
+                image_shift: -offset / (convergence * half_height),
+                ..*camera
+            }
+        };
+        (eye(-1.0), eye(1.0))
+    }
+}
+
+impl StereoLayout {
+    /// Put the two eyes' images, each `width` by `height` pixels, together into one. Returns the pixels and the size of
+    /// the combined image.
+    pub fn compose(self, left: &[Vec3], right: &[Vec3], (width, height): (usize, usize))
+        -> (Vec<Vec3>, (usize, usize)) {
+        match self {
+            StereoLayout::SideBySide => {
+                let pixels = left.chunks(width).zip(right.chunks(width))
+                    .flat_map(|(left_row, right_row)| left_row.iter().chain(right_row))
+                    .copied()
+                    .collect();
+                (pixels, (2 * width, height))
+            }
+            StereoLayout::TopBottom => (left.iter().chain(right).copied().collect(), (width, 2 * height)),
+            StereoLayout::Anaglyph => {
+                let pixels = left.iter().zip(right)
+                    .map(|(&left, &right)| apply(&ANAGLYPH_LEFT, left) + apply(&ANAGLYPH_RIGHT, right))
+                    .collect();
+                (pixels, (width, height))
+            }
+        }
+    }
+}
+
+fn apply(matrix: &[[f64; 3]; 3], color: Vec3) -> Vec3 {
+    // the matrices are made for colors in the displayable range
+    let (r, g, b) = (color.x.clamp(0.0, 1.0), color.y.clamp(0.0, 1.0), color.z.clamp(0.0, 1.0));
+    let row = |row: [f64; 3]| row[0] * r + row[1] * g + row[2] * b;
+    Vec3 { x: row(matrix[0]), y: row(matrix[1]), z: row(matrix[2]) }
+}
+
+impl FromStr for StereoLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "side-by-side" => Ok(StereoLayout::SideBySide),
+            "top-bottom" => Ok(StereoLayout::TopBottom),
+            "anaglyph" => Ok(StereoLayout::Anaglyph),
+            _ => Err(format!("unknown stereo layout {}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPS: f64 = 1E-9;
+
+    #[test]
+    fn eyes_converge_on_the_view_direction() {
+        let camera = Camera { position: (0, 0, 5).into(), look_at: Vec3::default(), ..Camera::default() };
+        let stereo = Stereo { ipd: 0.2, convergence: Some(3.0), layout: StereoLayout::Anaglyph };
+        let (left, right) = stereo.eyes(&camera);
+        assert!((left.position - Vec3::from((-0.1, 0.0, 5.0))).magnitude() < EPS);
+        assert!((right.position - Vec3::from((0.1, 0.0, 5.0))).magnitude() < EPS);
+        // both eyes look straight ahead, with the middle of each image on the convergence point
+        assert_eq!(left.basis().forward, camera.basis().forward);
+        let convergence_point = Vec3::from((0, 0, 2));
+        for eye in &[left, right] {
+            let (origin, dir) = eye.ray(50.0, 50.0, (100, 100)).unwrap();
+            assert!((origin + (convergence_point - origin).magnitude() * dir - convergence_point).magnitude() < EPS);
+            // a point off to the side at the convergence distance is in the same place in both images
+            let (_, corner_dir) = eye.ray(0.0, 0.0, (100, 100)).unwrap();
+            let corner = origin + (3.0 / -corner_dir.z) * corner_dir;
+            assert!((corner - Vec3::from((-3, 3, 2))).magnitude() < EPS);
+        }
+        assert_eq!(left.focus_distance, Some(5.0));
+    }
+
+    #[test]
+    fn side_by_side_keeps_rows_together() {
+        let left: Vec<Vec3> = (0..4).map(Vec3::from).collect();
+        let right: Vec<Vec3> = (10..14).map(Vec3::from).collect();
+        let (pixels, size) = StereoLayout::SideBySide.compose(&left, &right, (2, 2));
+        assert_eq!(size, (4, 2));
+        let xs: Vec<f64> = pixels.iter().map(|pixel| pixel.x).collect();
+        assert_eq!(xs, vec![0.0, 1.0, 10.0, 11.0, 2.0, 3.0, 12.0, 13.0]);
+    }
+
+    #[test]
+    fn top_bottom_stacks_the_eyes() {
+        let left: Vec<Vec3> = (0..4).map(Vec3::from).collect();
+        let right: Vec<Vec3> = (10..14).map(Vec3::from).collect();
+        let (pixels, size) = StereoLayout::TopBottom.compose(&left, &right, (2, 2));
+        assert_eq!(size, (2, 4));
+        let xs: Vec<f64> = pixels.iter().map(|pixel| pixel.x).collect();
+        assert_eq!(xs, vec![0.0, 1.0, 2.0, 3.0, 10.0, 11.0, 12.0, 13.0]);
+    }
+
+    #[test]
+    fn anaglyph_splits_the_eyes_between_the_filters() {
+        let white = Vec3::from(1);
+        let black = Vec3::default();
+        let (pixels, size) = StereoLayout::Anaglyph.compose(&[white, black, white], &[black, white, white], (3, 1));
+        assert_eq!(size, (3, 1));
+        // the left eye shows through the red filter and the right eye through the cyan one
+        assert!(pixels[0].x > 1.0 && pixels[0].y < 0.0 && pixels[0].z < 0.0);
+        assert!(pixels[1].x < 0.0 && pixels[1].y > 1.0 && pixels[1].z > 1.0);
+        // where the eyes agree the colors come through unchanged
+        assert!((pixels[2] - white).magnitude() < 1E-4);
+        // brighter than white is clamped first
+        let (bright, _) = StereoLayout::Anaglyph.compose(&[Vec3::from(3)], &[Vec3::from(3)], (1, 1));
+        assert!((bright[0] - white).magnitude() < 1E-4);
+    }
+}
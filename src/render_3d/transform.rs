@@ -0,0 +1,96 @@
+use super::material::Material;
+use super::scene_object::SceneObject;
+use crate::vec3::Vec3;
+use cgmath::{Deg, Quaternion, Rotation, Rotation3};
+
+/// Places an object in the world: scaled uniformly about its origin, then rotated, then translated.
+pub struct Transformed<O: SceneObject> {
+    pub object: O,
+    pub translation: Vec3,
+    pub rotation: Quaternion<f64>,
+    pub scale: f64,
+}
+
+impl<O: SceneObject> Transformed<O> {
+    /// Map a world space point into the object's own space.
+    fn to_local(&self, point: Vec3) -> Vec3 {
+        let unrotated: Vec3 = self.rotation.invert().rotate_vector((point - self.translation).into()).into();
+        (1.0 / self.scale) * unrotated
+    }
+}
+
+impl<O: SceneObject> SceneObject for Transformed<O> {
+    fn distance_to(&self, point: Vec3, t: f64) -> f64 {
+        // rotation and translation keep distances the same, uniform scale stretches them by the scale factor
+        self.object.distance_to(self.to_local(point), t) * self.scale
+    }
+
+    fn get_material(&self, t: f64) -> Material {
+        self.object.get_material(t)
+    }
+
+    fn material_at(&self, point: Vec3, normal: Vec3, t: f64) -> Material {
+        let local_normal = self.rotation.invert().rotate_vector(normal.into()).into();
+        self.object.material_at(self.to_local(point), local_normal, t)
+    }
+
+    fn normal(&self, p: Vec3, t: f64) -> Vec3 {
+        let local_normal = self.object.normal(self.to_local(p), t);
+        self.rotation.rotate_vector(local_normal.into()).into()
+    }
+}
+
+/// Rotation from euler angles in degrees, applied about the x axis, then y, then z.
+pub fn euler_rotation(degrees: Vec3) -> Quaternion<f64> {
+    Quaternion::from_angle_z(Deg(degrees.z))
+        * Quaternion::from_angle_y(Deg(degrees.y))
+        * Quaternion::from_angle_x(Deg(degrees.x))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_3d::primitives::Cuboid;
+
+    const EPS: f64 = 1E-9;
+
+    fn cuboid(translation: Vec3, rotation: Vec3, scale: f64) -> Transformed<Cuboid> {
+        Transformed {
+            object: Cuboid { half_size: (1.0, 2.0, 3.0).into(), material: Material::default() },
+            translation,
+            rotation: euler_rotation(rotation),
+            scale,
+        }
+    }
+
+    #[test]
+    fn uniform_scale_scales_distance() {
+        let object = cuboid((5, 0, 0).into(), Vec3::default(), 2.0);
+        // the x faces are now two units either side of the center, and distances are measured in world units
+        assert!((object.distance_to((11, 0, 0).into(), 0.0) - 4.0).abs() < EPS);
+        assert!((object.distance_to((5, 0, 0).into(), 0.0) + 2.0).abs() < EPS);
+        assert!((object.distance_to((5, 0, -8).into(), 0.0) - 2.0).abs() < EPS);
+    }
+
+    #[test]
+    fn normals_follow_the_rotation() {
+        // a quarter turn about z lays the cuboid's x faces along y
+        let object = cuboid(Vec3::default(), (0, 0, 90).into(), 1.0);
+        assert!((object.distance_to((0, 5, 0).into(), 0.0) - 4.0).abs() < EPS);
+        let normal = object.normal((0, 5, 0).into(), 0.0);
+        assert!((normal - Vec3::from((0, 1, 0))).magnitude() < 1E-6, "normal was {:?}", normal);
+        let side = object.normal((-5, 0, 0).into(), 0.0);
+        assert!((side - Vec3::from((-1, 0, 0))).magnitude() < 1E-6, "normal was {:?}", side);
+    }
+
+    #[test]
+    fn to_local_undoes_the_transform() {
+        let object = cuboid((1.0, -2.0, 3.5).into(), (30, 45, 60).into(), 0.5);
+        let local = Vec3::from((0.3, -0.7, 1.1));
+        let rotated: Vec3 = object.rotation.rotate_vector((object.scale * local).into()).into();
+        let world = object.translation + rotated;
+        assert!((object.to_local(world) - local).magnitude() < EPS);
+        // the origin of the object's space sits on the translation
+        assert!(object.to_local(object.translation).magnitude() < EPS);
+    }
+}
@@ -0,0 +1,540 @@
+use crate::animation::{self, Track};
+use crate::render_3d::camera::{CubeLayout, Projection};
+use crate::render_3d::coloring::{Gradient, GradientSource};
+use crate::render_3d::domain::{Axis, Mirror, Repeat};
+use crate::render_3d::csg::{Difference, Intersection, SmoothDifference, SmoothIntersection, SmoothUnion, Union};
+use crate::render_3d::fractals::{Julia, Mandelbrot, Mandelbulb, OrbitColoring, OrbitSource, MANDELBROT_COLOR};
+use crate::render_3d::light::Light;
+use crate::render_3d::material::Material;
+use crate::render_3d::raymarcher::{Integrator, ShadowMode};
+use crate::render_3d::primitives::{Capsule, Cone, Cuboid, Cylinder, Ellipsoid, Plane, RoundedCuboid, Torus};
+use crate::render_3d::scene_object::{SceneObject, Sphere};
+use crate::render_3d::sectioned::ZSectioned;
+use crate::render_3d::shading::ShadingModel;
+use crate::render_3d::stereo::StereoLayout;
+use crate::render_3d::transform::{euler_rotation, Transformed};
+use crate::palette::PaletteDesc;
+use crate::range;
+use crate::vec3::Vec3;
+use cgmath::Quaternion;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A scene loaded from a TOML file. Every setting is optional so that anything left out falls back to the command
+/// line (or its defaults), and anything given explicitly on the command line overrides the file.
+///
+/// ```toml
+/// [camera]
+/// position = [2, 4, 4]
+/// look_at = [0, 0, 0]
+/// fov = 67
+///
+/// [[lights]]
+/// type = "point"
+/// position = [2, 4, 4]
+///
+/// [[lights]]
+/// type = "ambient"
+/// color = [0.1, 0.1, 0.1]
+///
+/// [materials.red]
+/// albedo = [0.8, 0, 0]
+/// shininess = 50
+///
+/// [object]
+/// type = "z_sectioned"
+/// z = 0.0
+///
+/// [object.object]
+/// type = "julia"
+/// c = [-0.2, 0.6, 0.2, 0.2]
+/// material = "red"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SceneFile {
+    pub camera: CameraDesc,
+    /// Each `[[lights]]` table is one light. The command line's lights are used if there are none
+    pub lights: Option<Vec<Light>>,
+    pub shading: ShadingDesc,
+    pub marcher: MarcherDesc,
+    /// Materials that objects can refer to by name
+    pub materials: HashMap<String, Material>,
+    pub object: Option<ObjectDesc>,
+    /// Keyframes animating values in the rest of the file, applied frame by frame when rendering an animation
+    pub tracks: Vec<Track>,    /// Directory of the scene file, which palette file paths in it are relative to
+    #[serde(skip)]
+    pub dir: PathBuf,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraDesc {
+    pub position: Option<Vec3>,
+    pub look_at: Option<Vec3>,
+    pub up: Option<Vec3>,
+    /// Vertical field of view in degrees
+    #[serde(deserialize_with = "range::optional_fov")]
+    pub fov: Option<f64>,
+    /// Deprecated way of giving the field of view, which is `2 * atan(1 / zoom)`. Can't be combined with `fov`
+    #[serde(deserialize_with = "range::optional_positive")]
+    pub zoom: Option<f64>,
+    /// Degrees
+    pub roll: Option<f64>,
+    pub projection: Option<Projection>,
+    /// Width of the area an orthographic camera shows
+    #[serde(deserialize_with = "range::optional_positive")]
+    pub view_width: Option<f64>,
+    /// Degrees across the image circle of a fisheye camera
+    #[serde(deserialize_with = "range::optional_fisheye_angle")]
+    pub fisheye_angle: Option<f64>,
+    pub cube_layout: Option<CubeLayout>,
+    /// Radius of the lens, for depth of field
+    #[serde(deserialize_with = "range::optional_non_negative")]
+    pub aperture: Option<f64>,
+    #[serde(deserialize_with = "range::optional_positive")]
+    pub focus_distance: Option<f64>,
+    /// Renders a stereo pair in this layout
+    pub stereo: Option<StereoLayout>,
+    /// Interpupillary distance of the stereo pair
+    #[serde(deserialize_with = "range::optional_positive")]
+    pub ipd: Option<f64>,
+    #[serde(deserialize_with = "range::optional_positive")]
+    pub convergence: Option<f64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShadingDesc {
+    pub model: Option<ShadingModel>,
+    pub background_color: Option<Vec3>,
+    pub max_steps_color: Option<Vec3>,
+    pub shadows: Option<ShadowMode>,
+    #[serde(deserialize_with = "range::optional_positive")]
+    pub shadow_softness: Option<f64>,
+    /// Color of an ambient light replacing any in `lights`
+    pub ambient_color: Option<Vec3>,
+    /// Specular color and shininess replacing those of every material, from before objects had materials
+    pub specular_color: Option<Vec3>,
+    pub specular_shininess: Option<f64>,
+    #[serde(deserialize_with = "range::optional_non_negative")]
+    pub ao_strength: Option<f64>,
+    pub ao_samples: Option<u32>,
+    pub max_bounces: Option<u32>,
+    pub integrator: Option<Integrator>,
+    #[serde(deserialize_with = "range::optional_positive_int")]
+    pub spp: Option<u32>,
+    pub seed: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MarcherDesc {
+    #[serde(deserialize_with = "range::optional_positive_int")]
+    pub aa_level: Option<u32>,
+    pub backplane: Option<Vec3>,
+    #[serde(deserialize_with = "range::optional_positive_int")]
+    pub max_steps: Option<u32>,
+    #[serde(deserialize_with = "range::optional_positive")]
+    pub min_step: Option<f64>,
+}
+
+/// A node in the object tree. The `type` key selects the variant, wrapper objects nest their child in an `object`
+/// table and combinators nest their operands in `a` and `b` tables. A combinator with a non-zero `smooth` radius
+/// blends the two operands together. Leaf objects take a `material`, either the name of one of the scene's
+/// `[materials]` or an inline table.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ObjectDesc {
+    Julia {
+        c: [f64; 4],
+        #[serde(default)]
+        material: MaterialRef,
+        #[serde(default)]
+        coloring: Option<OrbitColoringDesc>,
+    },
+    Mandelbrot {
+        #[serde(default)]
+        w: f64,
+        #[serde(default = "default_mandelbrot_material")]
+        material: MaterialRef,
+        #[serde(default)]
+        coloring: Option<OrbitColoringDesc>,
+    },
+    Mandelbulb {
+        #[serde(default = "default_power", deserialize_with = "range::power")]
+        power: f64,
+        #[serde(default)]
+        material: MaterialRef,
+        #[serde(default)]
+        coloring: Option<OrbitColoringDesc>,
+    },
+    Sphere {
+        #[serde(default)]
+        center: Vec3,
+        radius: f64,
+        #[serde(default)]
+        material: MaterialRef,
+    },
+    Plane {
+        normal: Vec3,
+        #[serde(default)]
+        height: f64,
+        #[serde(default)]
+        material: MaterialRef,
+    },
+    #[serde(rename = "box")]
+    Cuboid {
+        half_size: Vec3,
+        #[serde(default)]
+        material: MaterialRef,
+    },
+    #[serde(rename = "rounded_box")]
+    RoundedCuboid {
+        half_size: Vec3,
+        radius: f64,
+        #[serde(default)]
+        material: MaterialRef,
+    },
+    Torus {
+        major_radius: f64,
+        minor_radius: f64,
+        #[serde(default)]
+        material: MaterialRef,
+    },
+    Cylinder {
+        radius: f64,
+        half_height: f64,
+        #[serde(default)]
+        material: MaterialRef,
+    },
+    Capsule {
+        a: Vec3,
+        b: Vec3,
+        radius: f64,
+        #[serde(default)]
+        material: MaterialRef,
+    },
+    Cone {
+        radius: f64,
+        height: f64,
+        #[serde(default)]
+        material: MaterialRef,
+    },
+    Ellipsoid {
+        radii: Vec3,
+        #[serde(default)]
+        material: MaterialRef,
+    },
+    ZSectioned {
+        z: f64,
+        object: Box<ObjectDesc>,
+    },
+    /// Scales the object, then rotates it by euler angles in degrees (about x, then y, then z), then translates it.
+    Transform {
+        #[serde(default)]
+        translate: Vec3,
+        #[serde(default)]
+        rotate: Vec3,
+        #[serde(default = "default_scale")]
+        scale: f64,
+        object: Box<ObjectDesc>,
+    },
+    /// Tiles the object with the given spacing along each axis (0 for no repetition). `count` limits the number of
+    /// copies along each axis, with 0 meaning unlimited.
+    Repeat {
+        spacing: Vec3,
+        #[serde(default)]
+        count: [u32; 3],
+        object: Box<ObjectDesc>,
+    },
+    /// Makes the object symmetric about the plane through the origin perpendicular to `axis` ("x", "y" or "z").
+    Mirror {
+        axis: Axis,
+        object: Box<ObjectDesc>,
+    },
+    /// Colors the object with a palette: a preset name or palette file path, a list of colors, or a table of stops.
+    /// With `by = "position"` (the default) the palette runs along the line from `start` to `end`, with
+    /// `by = "normal"` it depends on how closely the surface faces the direction from `start` to `end`.
+    Gradient {
+        #[serde(default = "default_gradient_source")]
+        by: GradientSource,
+        #[serde(default)]
+        start: Vec3,
+        end: Vec3,
+        palette: PaletteDesc,
+        object: Box<ObjectDesc>,
+    },
+    Union {
+        a: Box<ObjectDesc>,
+        b: Box<ObjectDesc>,
+        #[serde(default)]
+        smooth: f64,
+    },
+    Intersection {
+        a: Box<ObjectDesc>,
+        b: Box<ObjectDesc>,
+        #[serde(default)]
+        smooth: f64,
+    },
+    Difference {
+        a: Box<ObjectDesc>,
+        b: Box<ObjectDesc>,
+        #[serde(default)]
+        smooth: f64,
+    },
+}
+
+impl ObjectDesc {
+    /// Build the object tree, looking up named materials and palette files in `scene`.
+    pub fn build(&self, scene: &SceneFile) -> Result<Box<dyn SceneObject>, String> {
+        Ok(match *self {
+            ObjectDesc::Julia { c: [w, x, y, z], ref material, ref coloring } => Box::new(Julia {
+                c: Quaternion::new(w, x, y, z),
+                material: material.resolve(&scene.materials)?,
+                coloring: coloring.as_ref().map(|coloring| coloring.build(&scene.dir)).transpose()?,
+            }),
+            ObjectDesc::Mandelbrot { w, ref material, ref coloring } => Box::new(Mandelbrot {
+                w,
+                material: material.resolve(&scene.materials)?,
+                coloring: coloring.as_ref().map(|coloring| coloring.build(&scene.dir)).transpose()?,
+            }),
+            ObjectDesc::Mandelbulb { power, ref material, ref coloring } => Box::new(Mandelbulb {
+                power,
+                material: material.resolve(&scene.materials)?,
+                coloring: coloring.as_ref().map(|coloring| coloring.build(&scene.dir)).transpose()?,
+            }),
+            ObjectDesc::Sphere { center, radius, ref material } => Box::new(Sphere {
+                center,
+                radius,
+                material: material.resolve(&scene.materials)?,
+            }),
+            ObjectDesc::Plane { normal, height, ref material } => Box::new(Plane {
+                normal,
+                height,
+                material: material.resolve(&scene.materials)?,
+            }),
+            ObjectDesc::Cuboid { half_size, ref material } => Box::new(Cuboid {
+                half_size,
+                material: material.resolve(&scene.materials)?,
+            }),
+            ObjectDesc::RoundedCuboid { half_size, radius, ref material } => Box::new(RoundedCuboid {
+                half_size,
+                radius,
+                material: material.resolve(&scene.materials)?,
+            }),
+            ObjectDesc::Torus { major_radius, minor_radius, ref material } => Box::new(Torus {
+                major_radius,
+                minor_radius,
+                material: material.resolve(&scene.materials)?,
+            }),
+            ObjectDesc::Cylinder { radius, half_height, ref material } => Box::new(Cylinder {
+                radius,
+                half_height,
+                material: material.resolve(&scene.materials)?,
+            }),
+            ObjectDesc::Capsule { a, b, radius, ref material } => Box::new(Capsule {
+                a,
+                b,
+                radius,
+                material: material.resolve(&scene.materials)?,
+            }),
+            ObjectDesc::Cone { radius, height, ref material } => Box::new(Cone {
+                radius,
+                height,
+                material: material.resolve(&scene.materials)?,
+            }),
+            ObjectDesc::Ellipsoid { radii, ref material } => Box::new(Ellipsoid {
+                radii,
+                material: material.resolve(&scene.materials)?,
+            }),
+            ObjectDesc::ZSectioned { z, ref object } => Box::new(ZSectioned {
+                object: object.build(scene)?,
+                z,
+            }),
+            ObjectDesc::Transform { translate, rotate, scale, ref object } => {
+                // the distance estimate is divided by the scale, and a negative one would turn it inside out
+                if scale <= 0.0 {
+                    return Err(format!("transform scale must be greater than zero, not {}", scale));
+                }
+                Box::new(Transformed {
+                    object: object.build(scene)?,
+                    translation: translate,
+                    rotation: euler_rotation(rotate),
+                    scale,
+                })
+            }
+            ObjectDesc::Repeat { spacing, count, ref object } => Box::new(Repeat {
+                object: object.build(scene)?,
+                spacing,
+                count: [limit(count[0]), limit(count[1]), limit(count[2])],
+            }),
+            ObjectDesc::Mirror { axis, ref object } => Box::new(Mirror {
+                object: object.build(scene)?,
+                axis,
+            }),
+            ObjectDesc::Gradient { by, start, end, ref palette, ref object } => {
+                // the gradient runs along the direction from start to end, which there isn't one of if they're equal
+                if start == end {
+                    return Err(String::from("gradient start and end must be different points"));
+                }
+                Box::new(Gradient {
+                    object: object.build(scene)?,
+                    source: by,
+                    start,
+                    end,
+                    palette: palette.resolve(&scene.dir)?,
+                })
+            }
+            ObjectDesc::Union { ref a, ref b, smooth } if smooth > 0.0 => Box::new(SmoothUnion {
+                a: a.build(scene)?,
+                b: b.build(scene)?,
+                radius: smooth,
+            }),
+            ObjectDesc::Union { ref a, ref b, .. } => Box::new(Union {
+                a: a.build(scene)?,
+                b: b.build(scene)?,
+            }),
+            ObjectDesc::Intersection { ref a, ref b, smooth } if smooth > 0.0 => Box::new(SmoothIntersection {
+                a: a.build(scene)?,
+                b: b.build(scene)?,
+                radius: smooth,
+            }),
+            ObjectDesc::Intersection { ref a, ref b, .. } => Box::new(Intersection {
+                a: a.build(scene)?,
+                b: b.build(scene)?,
+            }),
+            ObjectDesc::Difference { ref a, ref b, smooth } if smooth > 0.0 => Box::new(SmoothDifference {
+                a: a.build(scene)?,
+                b: b.build(scene)?,
+                radius: smooth,
+            }),
+            ObjectDesc::Difference { ref a, ref b, .. } => Box::new(Difference {
+                a: a.build(scene)?,
+                b: b.build(scene)?,
+            }),
+        })
+    }
+}
+
+/// An object's material: the name of one of the scene's materials, or a material given inline.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum MaterialRef {
+    Named(String),
+    Inline(Material),
+}
+
+impl Default for MaterialRef {
+    fn default() -> Self {
+        MaterialRef::Inline(Material::default())
+    }
+}
+
+impl MaterialRef {
+    fn resolve(&self, materials: &HashMap<String, Material>) -> Result<Material, String> {
+        match self {
+            MaterialRef::Named(name) => materials
+                .get(name)
+                .copied()
+                .ok_or_else(|| format!("unknown material \"{}\"", name)),
+            MaterialRef::Inline(material) => Ok(*material),
+        }
+    }
+}
+
+/// Orbit coloring of a fractal, with its palette still to be looked up.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OrbitColoringDesc {
+    #[serde(flatten)]
+    pub source: OrbitSource,
+    pub palette: PaletteDesc,
+}
+
+impl OrbitColoringDesc {
+    fn build(&self, dir: &Path) -> Result<OrbitColoring, String> {
+        Ok(OrbitColoring { source: self.source.clone(), palette: self.palette.resolve(dir)? })
+    }
+}
+
+/// Repetition counts of 0 mean unlimited.
+fn limit(count: u32) -> Option<u32> {
+    if count == 0 {
+        None
+    } else {
+        Some(count)
+    }
+}
+
+fn default_gradient_source() -> GradientSource {
+    GradientSource::Position
+}
+
+/// The mandelbrot set has always been light blue, unlike the other objects.
+fn default_mandelbrot_material() -> MaterialRef {
+    MaterialRef::Inline(Material { albedo: MANDELBROT_COLOR, ..Material::default() })
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+fn default_power() -> f64 {
+    4.0
+}
+
+impl SceneFile {
+    /// Load the scene as it is at `frame` of an animation, with its tracks' values at that frame.
+    pub fn load(path: &Path, frame: u32) -> Result<SceneFile, SceneError> {
+        let mut scene = SceneFile::load_frame(path, frame)?;
+        scene.dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        if scene.camera.fov.is_some() && scene.camera.zoom.is_some() {
+            let message = String::from("camera fov and zoom can't both be given, and zoom is deprecated");
+            return Err(SceneError::Invalid(path.to_path_buf(), message));
+        }
+        Ok(scene)
+    }
+
+    fn load_frame(path: &Path, frame: u32) -> Result<SceneFile, SceneError> {
+        let source = fs::read_to_string(path).map_err(|e| SceneError::Io(path.to_path_buf(), e))?;
+        // parsed straight from the source first, so mistakes are reported with their line
+        let scene: SceneFile = toml::from_str(&source).map_err(|e| SceneError::Parse(path.to_path_buf(), e))?;
+        if scene.tracks.is_empty() {
+            return Ok(scene);
+        }
+
+        let value = source.parse().map_err(|e| SceneError::Parse(path.to_path_buf(), e))?;
+        let animated = animation::apply(&scene.tracks, &value, frame as f64)
+            .map_err(|e| SceneError::Animation(path.to_path_buf(), frame, e))?;
+        animated
+            .try_into()
+            .map_err(|e: toml::de::Error| SceneError::Animation(path.to_path_buf(), frame, e.to_string()))
+    }
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Animation(PathBuf, u32, String),
+    /// Settings that are each fine but can't be used together
+    Invalid(PathBuf, String),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io(path, e) => write!(f, "could not read scene file {}: {}", path.display(), e),
+            // toml's message already ends with "at line X column Y"
+            SceneError::Parse(path, e) => write!(f, "invalid scene file {}: {}", path.display(), e),
+            SceneError::Animation(path, frame, e) => {
+                write!(f, "invalid scene file {} at frame {}: {}", path.display(), frame, e)
+            }
+            SceneError::Invalid(path, e) => write!(f, "invalid scene file {}: {}", path.display(), e),
+        }
+    }
+}
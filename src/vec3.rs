@@ -1,7 +1,10 @@
 use std::ops::{Mul, Neg, Sub, Add};
+use cgmath::Vector3;
 use image::Rgba;
+use serde::Deserialize;
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(from = "[f64; 3]")]
 pub struct Vec3 {
     pub x: f64,
     pub y: f64,
@@ -46,6 +49,19 @@ impl Vec3 {
     pub fn lerp(self, other: Vec3, t: f64) -> Self {
         self + t * (other - self)
     }
+
+    pub fn abs(self) -> Self {
+        Vec3 { x: self.x.abs(), y: self.y.abs(), z: self.z.abs() }
+    }
+
+    /// Component-wise maximum
+    pub fn max(self, other: Vec3) -> Self {
+        Vec3 { x: self.x.max(other.x), y: self.y.max(other.y), z: self.z.max(other.z) }
+    }
+
+    pub fn max_component(self) -> f64 {
+        self.x.max(self.y).max(self.z)
+    }
 }
 
 impl Add<Vec3> for Vec3 {
@@ -80,6 +96,15 @@ impl Mul<Vec3> for f64 {
     }
 }
 
+/// Component-wise product, eg. for filtering a color by another color
+impl Mul<Vec3> for Vec3 {
+    type Output = Vec3;
+
+    fn mul(self, rhs: Vec3) -> Self::Output {
+        Vec3 { x: self.x * rhs.x, y: self.y * rhs.y, z: self.z * rhs.z }
+    }
+}
+
 impl Add<f64> for Vec3 {
     type Output = Vec3;
 
@@ -100,6 +125,24 @@ impl From<(f64, f64, f64)> for Vec3 {
     }
 }
 
+impl From<[f64; 3]> for Vec3 {
+    fn from([x, y, z]: [f64; 3]) -> Self {
+        Vec3 { x, y, z }
+    }
+}
+
+impl From<Vector3<f64>> for Vec3 {
+    fn from(v: Vector3<f64>) -> Self {
+        Vec3 { x: v.x, y: v.y, z: v.z }
+    }
+}
+
+impl From<Vec3> for Vector3<f64> {
+    fn from(v: Vec3) -> Self {
+        Vector3::new(v.x, v.y, v.z)
+    }
+}
+
 impl From<(i32, i32, i32)> for Vec3 {
     fn from((x, y, z): (i32, i32, i32)) -> Self {
         Vec3 { x: x as f64, y: y as f64, z: z as f64 }